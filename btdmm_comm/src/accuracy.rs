@@ -0,0 +1,206 @@
+//! Accuracy of the meters and uncertainty of their readings.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::DmmError;
+use crate::parser::Mode;
+use crate::units::Quantity;
+
+/// Accuracy of a meter, ±(percent of the reading + digits).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Accuracy {
+    pub percent: f64,
+    /// Counts of the last displayed digit.
+    pub digits: u32,
+}
+
+impl Accuracy {
+    /// Largest error allowed for `value`, displayed with `resolution`.
+    pub fn allowed(&self, value: f64, resolution: f64) -> f64 {
+        self.percent / 100.0 * value.abs() + self.digits as f64 * resolution
+    }
+}
+
+impl FromStr for Accuracy {
+    type Err = DmmError;
+
+    /// Parses `0.5% + 2d`, `0.5%` or `2d`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || DmmError::InvalidAccuracy(s.to_string());
+        let mut accuracy = Accuracy {
+            percent: 0.0,
+            digits: 0,
+        };
+        for term in s.split('+') {
+            let term = term.trim();
+            if let Some(percent) = term.strip_suffix('%') {
+                accuracy.percent = percent.trim().parse().map_err(|_| invalid())?;
+            } else if let Some(digits) = term.strip_suffix('d') {
+                accuracy.digits = digits.trim().parse().map_err(|_| invalid())?;
+            } else {
+                return Err(invalid());
+            }
+        }
+        if accuracy.percent < 0.0 {
+            return Err(invalid());
+        }
+        Ok(accuracy)
+    }
+}
+
+impl fmt::Display for Accuracy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "±({}% + {}d)", self.percent, self.digits)
+    }
+}
+
+/// Accuracy of a model in one mode, or in one range of it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AccuracySpec {
+    pub mode: Mode,
+    /// Unit displayed in the range the spec applies to, e.g. `mV`, or `None`
+    /// for every range of the mode.
+    pub unit: Option<&'static str>,
+    pub accuracy: Accuracy,
+}
+
+impl AccuracySpec {
+    const fn new(
+        mode: Mode,
+        unit: Option<&'static str>,
+        percent: f64,
+        digits: u32,
+    ) -> AccuracySpec {
+        AccuracySpec {
+            mode,
+            unit,
+            accuracy: Accuracy { percent, digits },
+        }
+    }
+
+    fn matches(&self, mode: Mode, unit: Option<&str>) -> bool {
+        self.mode == mode && (self.unit.is_none() || self.unit == unit)
+    }
+}
+
+/// Returns the accuracy of the first spec of `table` matching the mode and
+/// displayed unit.
+pub(crate) fn lookup(table: &[AccuracySpec], mode: Mode, unit: Option<&str>) -> Option<Accuracy> {
+    table
+        .iter()
+        .find(|spec| spec.matches(mode, unit))
+        .map(|spec| spec.accuracy)
+}
+
+/// Typical accuracy of the meters speaking this protocol, from the manuals of
+/// a few of them; the actual figures of a given meter may differ slightly.
+pub(crate) static TYPICAL_ACCURACY: [AccuracySpec; 13] = [
+    AccuracySpec::new(Mode::DcVoltage, Some("mV"), 0.8, 3),
+    AccuracySpec::new(Mode::DcVoltage, None, 0.5, 3),
+    AccuracySpec::new(Mode::AcVoltage, None, 1.0, 3),
+    AccuracySpec::new(Mode::DcCurrent, None, 1.2, 3),
+    AccuracySpec::new(Mode::AcCurrent, None, 1.5, 3),
+    AccuracySpec::new(Mode::Resistance, Some("MΩ"), 1.5, 3),
+    AccuracySpec::new(Mode::Resistance, None, 0.8, 3),
+    AccuracySpec::new(Mode::Diode, None, 1.0, 5),
+    AccuracySpec::new(Mode::Capacitance, Some("nF"), 4.0, 10),
+    AccuracySpec::new(Mode::Capacitance, None, 4.0, 5),
+    AccuracySpec::new(Mode::Frequency, None, 0.1, 2),
+    AccuracySpec::new(Mode::DutyCycle, None, 1.0, 2),
+    AccuracySpec::new(Mode::Temperature, None, 1.0, 3),
+];
+
+/// Interval the measured quantity lies in, given the accuracy of the meter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Uncertainty {
+    /// The displayed value.
+    pub value: f64,
+    /// Half-width of the interval, in the displayed unit.
+    pub margin: f64,
+    pub accuracy: Accuracy,
+}
+
+impl Uncertainty {
+    /// Computes the interval of `value`, displayed with `resolution`.
+    pub fn new(value: f64, resolution: f64, accuracy: Accuracy) -> Uncertainty {
+        Uncertainty {
+            value,
+            margin: accuracy.allowed(value, resolution),
+            accuracy,
+        }
+    }
+
+    pub fn low(&self) -> f64 {
+        self.value - self.margin
+    }
+
+    pub fn high(&self) -> f64 {
+        self.value + self.margin
+    }
+
+    pub fn contains(&self, value: f64) -> bool {
+        (self.low()..=self.high()).contains(&value)
+    }
+
+    /// Converts the interval from the unit `from` to `to` (see
+    /// `Quantity::convert`).
+    pub fn convert(&self, from: &str, to: &str) -> Result<Uncertainty, DmmError> {
+        // Convert the bounds, as temperatures aren't just scaled
+        let convert = |value| Quantity::new(value, from).convert(to).map(|q| q.value);
+        let (low, high) = (convert(self.low())?, convert(self.high())?);
+        Ok(Uncertainty {
+            value: convert(self.value)?,
+            margin: (high - low) / 2.0,
+            accuracy: self.accuracy,
+        })
+    }
+
+    /// Margin relative to the value, in percent.
+    pub fn percent(&self) -> Option<f64> {
+        (self.value != 0.0).then(|| self.margin / self.value.abs() * 100.0)
+    }
+}
+
+impl fmt::Display for Uncertainty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Two significant digits are enough for a margin
+        let decimals = if self.margin > 0.0 {
+            (1 - self.margin.log10().floor() as i32).max(0) as usize
+        } else {
+            0
+        };
+        write!(f, "{} ± {:.*}", self.value, decimals, self.margin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uncertainty() {
+        let accuracy: Accuracy = "0.5% + 3d".parse().unwrap();
+        assert_eq!(accuracy.to_string(), "±(0.5% + 3d)");
+
+        let uncertainty = Uncertainty::new(4.712, 0.001, accuracy);
+        assert!((uncertainty.margin - 0.02656).abs() < 1e-9);
+        assert!(uncertainty.contains(4.69));
+        assert!(!uncertainty.contains(4.74));
+        assert!((uncertainty.percent().unwrap() - 0.5637).abs() < 1e-4);
+        assert_eq!(uncertainty.to_string(), "4.712 ± 0.027");
+        let millivolts = uncertainty.convert("V", "mV").unwrap();
+        assert_eq!(millivolts.to_string(), "4712 ± 27");
+        let fahrenheit = Uncertainty::new(20.0, 1.0, accuracy)
+            .convert("°C", "°F")
+            .unwrap();
+        assert_eq!(fahrenheit.to_string(), "68 ± 5.6");
+
+        let table = &TYPICAL_ACCURACY;
+        let millivolts = lookup(table, Mode::DcVoltage, Some("mV")).unwrap();
+        assert_eq!(millivolts.percent, 0.8);
+        let volts = lookup(table, Mode::DcVoltage, Some("V")).unwrap();
+        assert_eq!(volts.percent, 0.5);
+        assert_eq!(lookup(table, Mode::Continuity, Some("Ω")), None);
+    }
+}
@@ -0,0 +1,135 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use btleplug::api::{Central, Manager as _};
+use btleplug::platform::{Adapter, Manager};
+
+use crate::DmmError;
+
+/// Which Bluetooth adapter to use, as given on the command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdapterSelector {
+    /// Position in the list returned by `list_adapters`.
+    Index(usize),
+    /// Adapter identifier, such as `hci1` on Linux.
+    Name(String),
+}
+
+impl FromStr for AdapterSelector {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.parse::<usize>() {
+            Ok(index) => AdapterSelector::Index(index),
+            Err(_) => AdapterSelector::Name(s.to_string()),
+        })
+    }
+}
+
+impl AdapterSelector {
+    fn matches(&self, index: usize, info: &str) -> bool {
+        match self {
+            AdapterSelector::Index(i) => index == *i,
+            AdapterSelector::Name(name) => {
+                adapter_id(info).eq_ignore_ascii_case(name) || info.eq_ignore_ascii_case(name)
+            }
+        }
+    }
+}
+
+impl fmt::Display for AdapterSelector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AdapterSelector::Index(index) => write!(f, "adapter #{}", index),
+            AdapterSelector::Name(name) => write!(f, "adapter {:?}", name),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BluetoothAdapter {
+    pub index: usize,
+    /// Platform description of the adapter, starting with its identifier.
+    pub info: String,
+    pub adapter: Adapter,
+}
+
+impl BluetoothAdapter {
+    /// Returns the identifier part of the description, e.g. `hci0`.
+    pub fn id(&self) -> &str {
+        adapter_id(&self.info)
+    }
+}
+
+fn adapter_id(info: &str) -> &str {
+    info.split_whitespace().next().unwrap_or_default()
+}
+
+pub async fn list_adapters(manager: &Manager) -> Result<Vec<BluetoothAdapter>, Box<dyn Error>> {
+    let mut adapters = Vec::new();
+    for (index, adapter) in manager.adapters().await?.into_iter().enumerate() {
+        let info = adapter.adapter_info().await?;
+        adapters.push(BluetoothAdapter {
+            index,
+            info,
+            adapter,
+        });
+    }
+    Ok(adapters)
+}
+
+pub async fn select_adapter(
+    manager: &Manager,
+    selector: &AdapterSelector,
+) -> Result<BluetoothAdapter, Box<dyn Error>> {
+    let adapters = list_adapters(manager).await?;
+    find_adapter(adapters, selector)
+}
+
+fn find_adapter(
+    adapters: Vec<BluetoothAdapter>,
+    selector: &AdapterSelector,
+) -> Result<BluetoothAdapter, Box<dyn Error>> {
+    let available = adapters
+        .iter()
+        .map(|a| format!("{}: {}", a.index, a.info))
+        .collect::<Vec<_>>();
+
+    match adapters
+        .into_iter()
+        .find(|a| selector.matches(a.index, &a.info))
+    {
+        Some(adapter) => Ok(adapter),
+        None if available.is_empty() => {
+            Err(DmmError::AdapterNotFound("no Bluetooth adapters available".to_string()).into())
+        }
+        None => Err(DmmError::AdapterNotFound(format!(
+            "{} not found, available adapters are {}",
+            selector,
+            available.join(", ")
+        ))
+        .into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adapter_selector() {
+        assert_eq!("1".parse(), Ok(AdapterSelector::Index(1)));
+        assert_eq!(
+            "hci1".parse(),
+            Ok(AdapterSelector::Name("hci1".to_string()))
+        );
+
+        let info = "hci1 (usb:v0A12p0001d8891)";
+        assert!(AdapterSelector::Index(1).matches(1, info));
+        assert!(!AdapterSelector::Index(0).matches(1, info));
+        assert!(AdapterSelector::Name("HCI1".to_string()).matches(1, info));
+        assert!(AdapterSelector::Name(info.to_string()).matches(1, info));
+        assert!(!AdapterSelector::Name("hci".to_string()).matches(1, info));
+    }
+}
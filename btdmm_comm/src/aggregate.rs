@@ -0,0 +1,312 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+use crate::DmmError;
+use crate::parser::Mode;
+use crate::timed::TimedMeasurement;
+
+/// Longest window or step, keeping the window bounds of readings taken today
+/// within the range of `DateTime`.
+const MAX_WINDOW: Duration = Duration::from_secs(1000 * 365 * 24 * 3600);
+
+/// Summary of the readings of one window, all taken in the same mode and unit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowSummary {
+    pub mode: Option<Mode>,
+    pub unit: Option<String>,
+    /// Start of the window, or the time of the mode change that split it.
+    pub start: DateTime<Utc>,
+    /// End of the window, or the time of the mode change that split it.
+    pub end: DateTime<Utc>,
+    /// All the readings, overloads and other text included.
+    pub count: usize,
+    /// Readings showing a number.
+    pub values: usize,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub mean: Option<f64>,
+    /// Last number displayed in the window.
+    pub last: Option<f64>,
+}
+
+#[derive(Debug)]
+struct Segment {
+    mode: Option<Mode>,
+    unit: Option<String>,
+    /// When the mode changed to this one.
+    start: DateTime<Utc>,
+    readings: VecDeque<(DateTime<Utc>, Option<f64>)>,
+    /// End of the next window to report.
+    next_end: DateTime<Utc>,
+}
+
+/// Downsamples a measurement stream into per-window summaries.
+///
+/// Windows are aligned on multiples of their step since the Unix epoch, so
+/// one minute windows start on the minute. A window is reported once a
+/// reading past its end arrives. When the mode or unit changes, the windows
+/// in progress end early and new ones start with the new mode.
+#[derive(Debug)]
+pub struct WindowAggregator {
+    length: TimeDelta,
+    step: TimeDelta,
+    segment: Option<Segment>,
+}
+
+impl WindowAggregator {
+    /// Back-to-back windows of `length`.
+    pub fn tumbling(length: Duration) -> Result<WindowAggregator, DmmError> {
+        WindowAggregator::sliding(length, length)
+    }
+
+    /// Windows of `length` reported every `step`, overlapping if `step` is
+    /// shorter. Both are rounded up to a millisecond and can't exceed a
+    /// thousand years.
+    pub fn sliding(length: Duration, step: Duration) -> Result<WindowAggregator, DmmError> {
+        let delta = |d: Duration| {
+            if d > MAX_WINDOW {
+                return Err(DmmError::InvalidWindow(format!("{:?} is too long", d)));
+            }
+            // Can't fail below the maximum
+            Ok(TimeDelta::from_std(d)
+                .unwrap()
+                .max(TimeDelta::milliseconds(1)))
+        };
+        Ok(WindowAggregator {
+            length: delta(length)?,
+            step: delta(step)?,
+            segment: None,
+        })
+    }
+
+    /// Feeds the next reading, returning the windows it completes.
+    pub fn push(&mut self, reading: &TimedMeasurement) -> Vec<WindowSummary> {
+        let measurement = &reading.measurement;
+        let time = reading.timestamp;
+        let mode = measurement.mode();
+        let mut summaries = Vec::new();
+
+        let same = self
+            .segment
+            .as_ref()
+            .is_some_and(|s| s.mode == mode && s.unit == measurement.value_unit);
+        let current_end = self.window_end(time);
+        if !same {
+            // Windows only start early when they follow a mode change
+            let start = match self.segment {
+                Some(_) => time,
+                None => DateTime::<Utc>::MIN_UTC,
+            };
+            summaries.extend(self.flush_at(time));
+            self.segment = Some(Segment {
+                mode,
+                unit: measurement.value_unit.clone(),
+                start,
+                readings: VecDeque::new(),
+                next_end: current_end,
+            });
+        }
+
+        let segment = self.segment.as_mut().unwrap();
+        while segment.next_end <= time {
+            let start = segment.next_end - self.length;
+            if let Some(summary) = summarize(segment, start, segment.next_end) {
+                summaries.push(summary);
+            }
+            segment.next_end += self.step;
+
+            let oldest = segment.next_end - self.length;
+            while segment.readings.front().is_some_and(|(t, _)| *t < oldest) {
+                segment.readings.pop_front();
+            }
+            // Skip the empty windows of a gap in the stream
+            if segment.readings.is_empty() {
+                segment.next_end = segment.next_end.max(current_end);
+            }
+        }
+        segment.readings.push_back((time, measurement.value()));
+
+        summaries
+    }
+
+    /// Ends the windows in progress, returning their summaries.
+    pub fn flush(&mut self) -> Vec<WindowSummary> {
+        let end = self
+            .segment
+            .as_ref()
+            .and_then(|s| s.readings.back())
+            .map(|(t, _)| *t + TimeDelta::nanoseconds(1));
+        match end {
+            Some(end) => self.flush_at(end),
+            None => Vec::new(),
+        }
+    }
+
+    fn flush_at(&mut self, end: DateTime<Utc>) -> Vec<WindowSummary> {
+        let Some(segment) = self.segment.take() else {
+            return Vec::new();
+        };
+        let Some(&(last, _)) = segment.readings.back() else {
+            return Vec::new();
+        };
+
+        // Every window that holds readings and hasn't been reported yet. The
+        // windows starting after the last reading are empty, however long
+        // the gap before `end`.
+        let end_of_readings = end.min(last + TimeDelta::nanoseconds(1));
+        let mut summaries = Vec::new();
+        let mut window_end = segment.next_end;
+        while window_end - self.length < end_of_readings {
+            if let Some(mut summary) = summarize(&segment, window_end - self.length, window_end) {
+                summary.end = summary.end.min(end);
+                summaries.push(summary);
+            }
+            window_end += self.step;
+        }
+        summaries
+    }
+
+    /// End of the first window of the segment holding `time`.
+    fn window_end(&self, time: DateTime<Utc>) -> DateTime<Utc> {
+        let step = self.step.num_microseconds().unwrap_or(i64::MAX);
+        let micros = time.timestamp_micros();
+        let aligned = micros - micros.rem_euclid(step) + step;
+        DateTime::from_timestamp_micros(aligned).unwrap_or(DateTime::<Utc>::MAX_UTC)
+    }
+}
+
+fn summarize(segment: &Segment, start: DateTime<Utc>, end: DateTime<Utc>) -> Option<WindowSummary> {
+    let readings: Vec<_> = segment
+        .readings
+        .iter()
+        .filter(|(t, _)| *t >= start && *t < end)
+        .collect();
+    if readings.is_empty() {
+        return None;
+    }
+
+    let values: Vec<f64> = readings.iter().filter_map(|(_, v)| *v).collect();
+    Some(WindowSummary {
+        mode: segment.mode,
+        unit: segment.unit.clone(),
+        start: start.max(segment.start),
+        end,
+        count: readings.len(),
+        values: values.len(),
+        min: values.iter().copied().reduce(f64::min),
+        max: values.iter().copied().reduce(f64::max),
+        mean: (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64),
+        last: values.last().copied(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::DisplayIcon;
+    use crate::timed::reading;
+
+    fn at(ms: i64) -> DateTime<Utc> {
+        DateTime::UNIX_EPOCH + TimeDelta::milliseconds(ms)
+    }
+
+    #[test]
+    fn test_tumbling() {
+        let mut aggregator = WindowAggregator::tumbling(Duration::from_secs(1)).unwrap();
+        let volt = DisplayIcon::Volt;
+
+        let mut summaries = Vec::new();
+        for (text, icon, ms) in [
+            ("1.000", volt.clone(), 100),
+            ("3.000", volt.clone(), 600),
+            (" .0L ", volt.clone(), 900),
+            ("2.000", volt.clone(), 1200),
+            ("4.000", volt.clone(), 1500),
+            // Mode change in the middle of a window
+            ("4.700", DisplayIcon::KiloOhm, 1700),
+            ("4.800", DisplayIcon::KiloOhm, 1800),
+            // Nothing for a while
+            ("4.900", DisplayIcon::KiloOhm, 5300),
+        ] {
+            summaries.extend(aggregator.push(&TimedMeasurement {
+                timestamp: at(ms),
+                ..reading(text, &[icon])
+            }));
+        }
+        summaries.extend(aggregator.flush());
+
+        assert_eq!(summaries.len(), 4);
+        let first = &summaries[0];
+        assert_eq!((first.start, first.end), (at(0), at(1000)));
+        assert_eq!(first.mode, Some(Mode::DcVoltage));
+        assert_eq!((first.count, first.values), (3, 2));
+        assert_eq!(first.min, Some(1.0));
+        assert_eq!(first.max, Some(3.0));
+        assert_eq!(first.mean, Some(2.0));
+        assert_eq!(first.last, Some(3.0));
+
+        let split = &summaries[1];
+        assert_eq!((split.start, split.end), (at(1000), at(1700)));
+        assert_eq!(split.mean, Some(3.0));
+
+        let ohms = &summaries[2];
+        assert_eq!((ohms.start, ohms.end), (at(1700), at(2000)));
+        assert_eq!(ohms.mode, Some(Mode::Resistance));
+        assert_eq!(ohms.unit.as_deref(), Some("kΩ"));
+        assert_eq!(ohms.count, 2);
+
+        let last = &summaries[3];
+        assert_eq!(last.start, at(5000));
+        assert_eq!(last.last, Some(4.9));
+    }
+
+    #[test]
+    fn test_sliding() {
+        let mut aggregator =
+            WindowAggregator::sliding(Duration::from_secs(2), Duration::from_secs(1)).unwrap();
+
+        let mut summaries = Vec::new();
+        for (i, text) in ["1.000", "2.000", "3.000", "4.000"].iter().enumerate() {
+            let ms = 500 + 1000 * i as i64;
+            summaries.extend(aggregator.push(&TimedMeasurement {
+                timestamp: at(ms),
+                ..reading(text, &[DisplayIcon::Volt])
+            }));
+        }
+
+        // Windows ending at 1 s, 2 s and 3 s
+        let means: Vec<_> = summaries.iter().map(|s| s.mean.unwrap()).collect();
+        assert_eq!(means, [1.0, 1.5, 2.5]);
+        assert_eq!(summaries[2].start, at(1000));
+        assert_eq!(summaries[2].count, 2);
+
+        let rest = aggregator.flush();
+        let means: Vec<_> = rest.iter().map(|s| s.mean.unwrap()).collect();
+        assert_eq!(means, [3.5, 4.0]);
+        assert_eq!(rest[1].end, at(3500) + TimeDelta::nanoseconds(1));
+    }
+
+    #[test]
+    fn test_window_limits() {
+        assert!(WindowAggregator::tumbling(Duration::MAX).is_err());
+        assert!(WindowAggregator::sliding(Duration::from_secs(60), Duration::MAX).is_err());
+
+        // A mode change after a long silence doesn't walk through every
+        // empty millisecond window of the gap
+        let mut aggregator = WindowAggregator::tumbling(Duration::ZERO).unwrap();
+        let volts = TimedMeasurement {
+            timestamp: at(0),
+            ..reading("1.000", &[DisplayIcon::Volt])
+        };
+        let ohms = TimedMeasurement {
+            timestamp: at(0) + TimeDelta::days(365),
+            ..reading("4.700", &[DisplayIcon::KiloOhm])
+        };
+        assert!(aggregator.push(&volts).is_empty());
+        let summaries = aggregator.push(&ohms);
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].last, Some(1.0));
+    }
+}
@@ -0,0 +1,527 @@
+//! Alarms raised by rules over the measurement stream.
+//!
+//! Rules can be read from a text file with one rule per line; blank lines and
+//! lines starting with `#` are ignored:
+//!
+//! ```text
+//! rail-low: dc-volts below 3.0 for 2s hysteresis 0.1 => log, bell
+//! too-hot: temperature above 80 => run notify-send "Too hot"
+//! overload: overload => bell
+//! battery: low-battery
+//! ```
+//!
+//! A rule is `<name>: <condition>`, where the condition is
+//! `<mode> below <value>`, `<mode> above <value>`, `overload` (in any mode),
+//! `<mode> overload` or `low-battery`. Modes are named as in scenarios
+//! (`dc-volts`, `ohms`...) and values are in the base unit of the mode, with
+//! the `n`, `u`, `m`, `k` and `M` suffixes. The options `for <duration>` and
+//! `hysteresis <value>` may follow, then `=>` and a comma separated list of
+//! actions: `log`, `bell` or `run <command>`, which takes the rest of the line
+//! and so comes last. Rules without actions log.
+
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process;
+use std::time::{Duration, Instant};
+
+use crate::DmmError;
+use crate::parser::{DisplayIcon, Mode};
+use crate::timed::TimedMeasurement;
+use crate::units::{parse_duration, parse_quantity};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    Below {
+        mode: Mode,
+        threshold: f64,
+    },
+    Above {
+        mode: Mode,
+        threshold: f64,
+    },
+    /// The meter shows "0L", in the given mode or in any mode.
+    Overload(Option<Mode>),
+    LowBattery,
+}
+
+/// Built-in alarm actions, as named in rule files.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    /// Prints raised and cleared alarms to stderr.
+    Log,
+    /// Rings the terminal bell when an alarm is raised.
+    Bell,
+    /// Runs a shell command when an alarm is raised. The command gets the
+    /// alarm in the `ALARM_NAME`, `ALARM_VALUE` and `ALARM_UNIT` variables.
+    Run(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlarmRule {
+    pub name: String,
+    pub condition: Condition,
+    /// How long the condition must hold before the alarm is raised.
+    pub duration: Duration,
+    /// How far back past the threshold the reading must go to clear the alarm.
+    pub hysteresis: f64,
+    pub actions: Vec<Action>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlarmState {
+    Raised,
+    Cleared,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlarmEvent {
+    pub rule: String,
+    pub state: AlarmState,
+    /// The reading that raised or cleared the alarm.
+    pub reading: TimedMeasurement,
+}
+
+/// Reaction to alarm events; closures taking an `&AlarmEvent` are actions.
+pub trait AlarmAction: Send {
+    fn fire(&mut self, event: &AlarmEvent);
+}
+
+impl<F: FnMut(&AlarmEvent) + Send> AlarmAction for F {
+    fn fire(&mut self, event: &AlarmEvent) {
+        self(event)
+    }
+}
+
+impl AlarmAction for Action {
+    fn fire(&mut self, event: &AlarmEvent) {
+        let measurement = &event.reading.measurement;
+        let value = measurement.value().map_or_else(
+            || format!("{:?}", measurement.displayed_value),
+            |v| v.to_string(),
+        );
+        let unit = measurement.value_unit.as_deref().unwrap_or("");
+
+        match (self, event.state) {
+            (Action::Log, AlarmState::Raised) => {
+                eprintln!("Alarm {} raised: {} {}", event.rule, value, unit)
+            }
+            (Action::Log, AlarmState::Cleared) => {
+                eprintln!("Alarm {} cleared: {} {}", event.rule, value, unit)
+            }
+            (Action::Bell, AlarmState::Raised) => {
+                eprint!("\x07");
+                let _ = io::stderr().flush();
+            }
+            (Action::Run(command), AlarmState::Raised) => {
+                let mut shell = if cfg!(windows) {
+                    let mut shell = process::Command::new("cmd");
+                    shell.arg("/C");
+                    shell
+                } else {
+                    let mut shell = process::Command::new("sh");
+                    shell.arg("-c");
+                    shell
+                };
+                let child = shell
+                    .arg(&*command)
+                    .env("ALARM_NAME", &event.rule)
+                    .env("ALARM_VALUE", &value)
+                    .env("ALARM_UNIT", unit)
+                    .spawn();
+                match child {
+                    // Reap the command without holding up the stream
+                    Ok(mut child) => drop(std::thread::spawn(move || child.wait())),
+                    Err(err) => {
+                        eprintln!("Alarm {}: cannot run {:?}: {}", event.rule, command, err)
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+struct RuleState {
+    rule: AlarmRule,
+    /// When the condition started to hold, while the alarm is not raised yet.
+    pending: Option<Instant>,
+    raised: bool,
+}
+
+impl RuleState {
+    fn holds(&self, reading: &TimedMeasurement) -> bool {
+        let measurement = &reading.measurement;
+        let mode = measurement.mode();
+        let value = measurement.base_value();
+
+        match self.rule.condition {
+            // Frames without a number ("----") tell nothing about the threshold
+            Condition::Below { .. } | Condition::Above { .. }
+                if value.is_none() && !measurement.is_overload() =>
+            {
+                self.raised
+            }
+            Condition::Below { mode: m, threshold } => {
+                let limit = if self.raised {
+                    threshold + self.rule.hysteresis
+                } else {
+                    threshold
+                };
+                mode == Some(m) && value.is_some_and(|v| v < limit)
+            }
+            Condition::Above { mode: m, threshold } => {
+                let limit = if self.raised {
+                    threshold - self.rule.hysteresis
+                } else {
+                    threshold
+                };
+                // An overload is above any threshold
+                mode == Some(m) && (measurement.is_overload() || value.is_some_and(|v| v > limit))
+            }
+            Condition::Overload(m) => {
+                measurement.is_overload() && m.is_none_or(|m| mode == Some(m))
+            }
+            Condition::LowBattery => measurement.has_icon(&DisplayIcon::LowBattery),
+        }
+    }
+}
+
+/// Evaluates alarm rules over a measurement stream and dispatches the raised
+/// and cleared alarms to the actions of the rules.
+pub struct AlarmEngine {
+    rules: Vec<RuleState>,
+    actions: Vec<Box<dyn AlarmAction>>,
+}
+
+impl AlarmEngine {
+    pub fn new(rules: Vec<AlarmRule>) -> AlarmEngine {
+        AlarmEngine {
+            rules: rules
+                .into_iter()
+                .map(|rule| RuleState {
+                    rule,
+                    pending: None,
+                    raised: false,
+                })
+                .collect(),
+            actions: Vec::new(),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<AlarmEngine, DmmError> {
+        let mut rules = Vec::new();
+
+        for (lineno, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let rule = parse_rule(line)
+                .map_err(|what| DmmError::InvalidAlarm(format!("line {}: {}", lineno + 1, what)))?;
+            rules.push(rule);
+        }
+
+        Ok(AlarmEngine::new(rules))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<AlarmEngine, Box<dyn Error>> {
+        Ok(AlarmEngine::parse(&fs::read_to_string(path)?)?)
+    }
+
+    /// Adds an action fired on the events of every rule, on top of the
+    /// actions of the rules.
+    pub fn on_alarm(mut self, action: impl AlarmAction + 'static) -> Self {
+        self.actions.push(Box::new(action));
+        self
+    }
+
+    pub fn rules(&self) -> impl Iterator<Item = &AlarmRule> {
+        self.rules.iter().map(|state| &state.rule)
+    }
+
+    /// Rules whose alarm is currently raised.
+    pub fn raised(&self) -> impl Iterator<Item = &AlarmRule> {
+        self.rules
+            .iter()
+            .filter(|state| state.raised)
+            .map(|state| &state.rule)
+    }
+
+    /// Feeds the next reading, firing and returning the alarms it raised or
+    /// cleared.
+    pub fn push(&mut self, reading: &TimedMeasurement) -> Vec<AlarmEvent> {
+        let mut events = Vec::new();
+
+        for state in &mut self.rules {
+            let holds = state.holds(reading);
+            let changed = match (holds, state.raised) {
+                (true, false) => {
+                    let since = *state.pending.get_or_insert(reading.received);
+                    reading.received.saturating_duration_since(since) >= state.rule.duration
+                }
+                (false, true) => true,
+                (false, false) => {
+                    state.pending = None;
+                    false
+                }
+                (true, true) => false,
+            };
+            if !changed {
+                continue;
+            }
+
+            state.raised = !state.raised;
+            state.pending = None;
+            let event = AlarmEvent {
+                rule: state.rule.name.clone(),
+                state: if state.raised {
+                    AlarmState::Raised
+                } else {
+                    AlarmState::Cleared
+                },
+                reading: reading.clone(),
+            };
+            for action in &mut state.rule.actions {
+                action.fire(&event);
+            }
+            for action in &mut self.actions {
+                action.fire(&event);
+            }
+            events.push(event);
+        }
+
+        events
+    }
+}
+
+fn parse_rule(line: &str) -> Result<AlarmRule, String> {
+    let (name, rest) = line
+        .split_once(':')
+        .ok_or("expected \"<name>: <condition>\"")?;
+    let name = name.trim();
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Err(format!("invalid rule name {:?}", name));
+    }
+    let (condition, actions) = match rest.split_once("=>") {
+        Some((condition, actions)) => (condition, Some(actions)),
+        None => (rest, None),
+    };
+
+    let quantity = |token: Option<&&str>| {
+        token
+            .and_then(|t| parse_quantity(t))
+            .ok_or_else(|| "expected a value".to_string())
+    };
+
+    let tokens: Vec<_> = condition.split_whitespace().collect();
+    let (condition, options) = match tokens.as_slice() {
+        ["overload", options @ ..] => (Condition::Overload(None), options),
+        ["low-battery", options @ ..] => (Condition::LowBattery, options),
+        [mode, "overload", options @ ..] => (
+            Condition::Overload(Some(mode.parse().map_err(|e: DmmError| e.to_string())?)),
+            options,
+        ),
+        [mode, comparison @ ("below" | "above"), rest @ ..] => {
+            let mode = mode.parse().map_err(|e: DmmError| e.to_string())?;
+            let threshold = quantity(rest.first())?;
+            let condition = match *comparison {
+                "below" => Condition::Below { mode, threshold },
+                _ => Condition::Above { mode, threshold },
+            };
+            (condition, &rest[1..])
+        }
+        _ => return Err("expected below, above, overload or low-battery".to_string()),
+    };
+
+    let mut duration = Duration::ZERO;
+    let mut hysteresis = 0.0;
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        match *option {
+            "for" => {
+                duration = options
+                    .next()
+                    .and_then(|t| parse_duration(t))
+                    .ok_or("expected a duration")?
+            }
+            "hysteresis" => hysteresis = quantity(options.next())?,
+            other => return Err(format!("unknown option {:?}", other)),
+        }
+    }
+
+    let actions = match actions {
+        Some(actions) => parse_actions(actions)?,
+        None => vec![Action::Log],
+    };
+
+    Ok(AlarmRule {
+        name: name.to_string(),
+        condition,
+        duration,
+        hysteresis,
+        actions,
+    })
+}
+
+fn parse_actions(mut actions: &str) -> Result<Vec<Action>, String> {
+    let mut parsed = Vec::new();
+    loop {
+        let action = actions.trim_start();
+        // The command is the rest of the line, commas included
+        if let Some(command) = action
+            .strip_prefix("run")
+            .filter(|c| c.starts_with(char::is_whitespace) && !c.trim().is_empty())
+        {
+            parsed.push(Action::Run(command.trim().to_string()));
+            return Ok(parsed);
+        }
+        let (action, rest) = match action.split_once(',') {
+            Some((action, rest)) => (action, Some(rest)),
+            None => (action, None),
+        };
+        match action.trim() {
+            "log" => parsed.push(Action::Log),
+            "bell" => parsed.push(Action::Bell),
+            other => return Err(format!("unknown action {:?}", other)),
+        }
+        match rest {
+            Some(rest) => actions = rest,
+            None => return Ok(parsed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::timed::reading;
+
+    #[test]
+    fn test_parse_rules() {
+        let engine = AlarmEngine::parse(
+            "# Supply rail\n\
+             rail-low: dc-volts below 3.0 for 2s hysteresis 100m => log, bell\n\
+             \n\
+             too-hot: temperature above 80 => run notify-send \"Too hot\"\n\
+             overload: ohms overload\n\
+             battery: low-battery => bell\n",
+        )
+        .unwrap();
+
+        let rules: Vec<_> = engine.rules().collect();
+        assert_eq!(rules.len(), 4);
+        assert_eq!(rules[0].name, "rail-low");
+        assert_eq!(
+            rules[0].condition,
+            Condition::Below {
+                mode: Mode::DcVoltage,
+                threshold: 3.0
+            }
+        );
+        assert_eq!(rules[0].duration, Duration::from_secs(2));
+        assert_eq!(rules[0].hysteresis, 0.1);
+        assert_eq!(rules[0].actions, vec![Action::Log, Action::Bell]);
+        assert_eq!(
+            rules[1].actions,
+            vec![Action::Run("notify-send \"Too hot\"".to_string())]
+        );
+        assert_eq!(
+            rules[2].condition,
+            Condition::Overload(Some(Mode::Resistance))
+        );
+        assert_eq!(rules[2].actions, vec![Action::Log]);
+        assert_eq!(rules[3].condition, Condition::LowBattery);
+
+        let err = AlarmEngine::parse("a: volts below 3").err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Invalid alarm rules: line 1: Unknown mode: volts"
+        );
+        assert!(AlarmEngine::parse("a: dc-volts below").is_err());
+        assert!(AlarmEngine::parse("a: overload => beep").is_err());
+        assert!(AlarmEngine::parse("a: overload => run ").is_err());
+
+        let engine = AlarmEngine::parse("a: overload => bell, run echo \"1, 2\" >> log").unwrap();
+        assert_eq!(
+            engine.rules().next().unwrap().actions,
+            vec![
+                Action::Bell,
+                Action::Run("echo \"1, 2\" >> log".to_string())
+            ]
+        );
+        assert!(AlarmEngine::parse("dc-volts below 3").is_err());
+    }
+
+    #[test]
+    fn test_alarms() {
+        let start = Instant::now();
+        let volts = |text, ms| TimedMeasurement {
+            received: start + Duration::from_millis(ms),
+            ..reading(text, &[DisplayIcon::Volt, DisplayIcon::DC])
+        };
+
+        let fired = Arc::new(Mutex::new(Vec::new()));
+        let log = fired.clone();
+        let mut engine = AlarmEngine::new(vec![
+            AlarmRule {
+                name: "low".to_string(),
+                condition: Condition::Below {
+                    mode: Mode::DcVoltage,
+                    threshold: 3.0,
+                },
+                duration: Duration::from_secs(2),
+                hysteresis: 0.1,
+                actions: Vec::new(),
+            },
+            AlarmRule {
+                name: "overload".to_string(),
+                condition: Condition::Overload(None),
+                duration: Duration::ZERO,
+                hysteresis: 0.0,
+                actions: Vec::new(),
+            },
+        ])
+        .on_alarm(move |event: &AlarmEvent| {
+            log.lock().unwrap().push((event.rule.clone(), event.state));
+        });
+
+        // A short dip doesn't count
+        assert!(engine.push(&volts("3.300", 0)).is_empty());
+        assert!(engine.push(&volts("2.900", 500)).is_empty());
+        assert!(engine.push(&volts("3.200", 1000)).is_empty());
+        // A long one does
+        assert!(engine.push(&volts("2.900", 1500)).is_empty());
+        assert!(engine.push(&volts("2.950", 2500)).is_empty());
+        let events = engine.push(&volts("2.980", 3500));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].state, AlarmState::Raised);
+        assert_eq!(engine.raised().count(), 1);
+        // A frame without a number leaves it raised
+        assert!(engine.push(&volts("----", 3750)).is_empty());
+        assert_eq!(engine.raised().count(), 1);
+        // Within the hysteresis the alarm stays
+        assert!(engine.push(&volts("3.050", 4000)).is_empty());
+        let events = engine.push(&volts("3.150", 4500));
+        assert_eq!(events[0].state, AlarmState::Cleared);
+
+        let events = engine.push(&volts(" .0L ", 5000));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].rule, "overload");
+        engine.push(&volts("3.300", 5500));
+
+        assert_eq!(
+            *fired.lock().unwrap(),
+            vec![
+                ("low".to_string(), AlarmState::Raised),
+                ("low".to_string(), AlarmState::Cleared),
+                ("overload".to_string(), AlarmState::Raised),
+                ("overload".to_string(), AlarmState::Cleared),
+            ]
+        );
+    }
+}
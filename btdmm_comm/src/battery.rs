@@ -0,0 +1,402 @@
+use std::fmt;
+use std::io::{self, Write};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::power::PowerSample;
+
+/// Samples past the cutoff in a row needed to end the test, so a dip on a
+/// load step doesn't end it early.
+const CUTOFF_SAMPLES: usize = 3;
+
+/// Voltage at which a battery test ends.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cutoff {
+    /// Discharge test, ending when the voltage drops below the value.
+    Below(f64),
+    /// Charge test, ending when the voltage rises above the value.
+    Above(f64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    Cutoff,
+    Duration,
+    /// The test was stopped from the outside, e.g. the meters went away.
+    Interrupted,
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            StopReason::Cutoff => "cutoff voltage reached",
+            StopReason::Duration => "duration elapsed",
+            StopReason::Interrupted => "interrupted",
+        })
+    }
+}
+
+/// Records the voltage and current of a battery under charge or discharge
+/// until a cutoff voltage or a duration is reached.
+///
+/// The samples come from a `PowerMeter` pairing a meter on the battery
+/// voltage and a meter on its current. Sudden changes of the current (load
+/// steps) give estimates of the internal resistance of the battery.
+#[derive(Debug, Clone)]
+pub struct BatteryTest {
+    cutoff: Option<Cutoff>,
+    max_duration: Option<Duration>,
+    min_step: f64,
+    samples: Vec<PowerSample>,
+    past_cutoff: usize,
+    /// Voltage and current before a load step, and when the current reading
+    /// showing the step was received, until a voltage reading follows.
+    step: Option<(f64, f64, DateTime<Utc>)>,
+    resistances: Vec<f64>,
+    stopped: Option<StopReason>,
+}
+
+impl Default for BatteryTest {
+    fn default() -> BatteryTest {
+        BatteryTest {
+            cutoff: None,
+            max_duration: None,
+            min_step: 0.01,
+            samples: Vec::new(),
+            past_cutoff: 0,
+            step: None,
+            resistances: Vec::new(),
+            stopped: None,
+        }
+    }
+}
+
+impl BatteryTest {
+    pub fn new() -> BatteryTest {
+        BatteryTest::default()
+    }
+
+    pub fn cutoff(mut self, cutoff: Cutoff) -> Self {
+        self.cutoff = Some(cutoff);
+        self
+    }
+
+    pub fn max_duration(mut self, duration: Duration) -> Self {
+        self.max_duration = Some(duration);
+        self
+    }
+
+    /// Smallest change of the current, in amperes, taken as a load step
+    /// (10 mA by default).
+    pub fn load_step(mut self, amperes: f64) -> Self {
+        self.min_step = amperes;
+        self
+    }
+
+    /// Records the next sample, returning why the test should stop once it's
+    /// over. Samples pushed after that are ignored.
+    pub fn push(&mut self, sample: PowerSample) -> Option<StopReason> {
+        if self.stopped.is_some() {
+            return self.stopped;
+        }
+
+        // The meters update one at a time, so the voltage under the new load
+        // is only known once a voltage reading follows the current step
+        if let Some(last) = self.samples.last() {
+            if self.step.is_none() && (sample.current - last.current).abs() >= self.min_step {
+                self.step = Some((last.voltage, last.current, sample.current_timestamp));
+            }
+        }
+        if let Some((voltage, current, at)) = self.step {
+            if sample.voltage_timestamp >= at {
+                let current_step = sample.current - current;
+                if current_step.abs() >= self.min_step {
+                    self.resistances
+                        .push(((sample.voltage - voltage) / current_step).abs());
+                }
+                self.step = None;
+            }
+        }
+        self.samples.push(sample);
+
+        let past_cutoff = match self.cutoff {
+            Some(Cutoff::Below(volts)) => sample.voltage < volts,
+            Some(Cutoff::Above(volts)) => sample.voltage > volts,
+            None => false,
+        };
+        self.past_cutoff = if past_cutoff { self.past_cutoff + 1 } else { 0 };
+
+        if self.past_cutoff >= CUTOFF_SAMPLES {
+            self.stopped = Some(StopReason::Cutoff);
+        } else if self
+            .max_duration
+            .is_some_and(|max| self.elapsed().is_some_and(|elapsed| elapsed >= max))
+        {
+            self.stopped = Some(StopReason::Duration);
+        }
+        self.stopped
+    }
+
+    /// Ends the test before its cutoff or duration.
+    pub fn stop(&mut self) {
+        self.stopped.get_or_insert(StopReason::Interrupted);
+    }
+
+    pub fn stopped(&self) -> Option<StopReason> {
+        self.stopped
+    }
+
+    pub fn samples(&self) -> &[PowerSample] {
+        &self.samples
+    }
+
+    fn elapsed(&self) -> Option<Duration> {
+        let (first, last) = (self.samples.first()?, self.samples.last()?);
+        (last.timestamp - first.timestamp).to_std().ok()
+    }
+
+    pub fn report(&self) -> BatteryReport {
+        let first = self.samples.first();
+        let last = self.samples.last();
+        let delta = |f: fn(&PowerSample) -> f64| match (first, last) {
+            (Some(first), Some(last)) => (f(last) - f(first)).abs(),
+            _ => 0.0,
+        };
+
+        let mut resistances = self.resistances.clone();
+        resistances.sort_by(f64::total_cmp);
+        let internal_resistance = match resistances.len() {
+            0 => None,
+            n if n % 2 == 1 => Some(resistances[n / 2]),
+            n => Some((resistances[n / 2 - 1] + resistances[n / 2]) / 2.0),
+        };
+
+        BatteryReport {
+            started: first.map(|s| s.timestamp),
+            duration: self.elapsed().unwrap_or_default(),
+            samples: self.samples.len(),
+            start_voltage: first.map(|s| s.voltage),
+            end_voltage: last.map(|s| s.voltage),
+            min_voltage: self.samples.iter().map(|s| s.voltage).reduce(f64::min),
+            max_voltage: self.samples.iter().map(|s| s.voltage).reduce(f64::max),
+            mean_current: (!self.samples.is_empty()).then(|| {
+                self.samples.iter().map(|s| s.current).sum::<f64>() / self.samples.len() as f64
+            }),
+            capacity: delta(|s| s.charge) * 1000.0,
+            energy: delta(|s| s.energy),
+            internal_resistance,
+            load_steps: self.resistances.len(),
+            stopped: self.stopped,
+        }
+    }
+
+    /// Writes the recorded samples as CSV.
+    pub fn write_csv(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(
+            writer,
+            "time,voltage_v,current_a,power_w,energy_wh,charge_ah"
+        )?;
+        for s in &self.samples {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{}",
+                s.timestamp.to_rfc3339(),
+                s.voltage,
+                s.current,
+                s.power,
+                s.energy,
+                s.charge
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Outcome of a battery test.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatteryReport {
+    pub started: Option<DateTime<Utc>>,
+    pub duration: Duration,
+    pub samples: usize,
+    pub start_voltage: Option<f64>,
+    pub end_voltage: Option<f64>,
+    pub min_voltage: Option<f64>,
+    pub max_voltage: Option<f64>,
+    /// In amperes.
+    pub mean_current: Option<f64>,
+    /// Charge that went through the battery, in mAh.
+    pub capacity: f64,
+    /// In Wh.
+    pub energy: f64,
+    /// Median of the estimates from the load steps, in ohms.
+    pub internal_resistance: Option<f64>,
+    pub load_steps: usize,
+    pub stopped: Option<StopReason>,
+}
+
+impl fmt::Display for BatteryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let volts = |v: Option<f64>| v.map_or("-".to_string(), |v| format!("{:.3} V", v));
+
+        if let Some(started) = self.started {
+            writeln!(f, "Started:     {}", started.to_rfc3339())?;
+        }
+        let seconds = self.duration.as_secs();
+        writeln!(
+            f,
+            "Duration:    {}:{:02}:{:02} ({} samples)",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60,
+            self.samples
+        )?;
+        if let Some(reason) = self.stopped {
+            writeln!(f, "Stopped:     {}", reason)?;
+        }
+        writeln!(
+            f,
+            "Voltage:     {} -> {} (min {}, max {})",
+            volts(self.start_voltage),
+            volts(self.end_voltage),
+            volts(self.min_voltage),
+            volts(self.max_voltage)
+        )?;
+        if let Some(current) = self.mean_current {
+            writeln!(f, "Current:     {:.1} mA on average", current * 1000.0)?;
+        }
+        writeln!(f, "Capacity:    {:.1} mAh", self.capacity)?;
+        writeln!(f, "Energy:      {:.3} Wh", self.energy)?;
+        match self.internal_resistance {
+            Some(r) => write!(
+                f,
+                "Resistance:  {:.1} mΩ ({} load steps)",
+                r * 1000.0,
+                self.load_steps
+            ),
+            None => write!(f, "Resistance:  no load step"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+
+    use super::*;
+    use crate::parser::DisplayIcon;
+    use crate::power::PowerMeter;
+    use crate::timed::{TimedMeasurement, reading};
+
+    fn sample(seconds: i64, voltage: f64, current: f64, charge: f64) -> PowerSample {
+        let timestamp = DateTime::UNIX_EPOCH + TimeDelta::seconds(seconds);
+        PowerSample {
+            timestamp,
+            voltage,
+            current,
+            power: voltage * current,
+            energy: charge * voltage,
+            charge,
+            voltage_timestamp: timestamp,
+            current_timestamp: timestamp,
+        }
+    }
+
+    #[test]
+    fn test_discharge() {
+        let mut test = BatteryTest::new()
+            .cutoff(Cutoff::Below(3.0))
+            .max_duration(Duration::from_secs(3600));
+
+        assert_eq!(test.push(sample(0, 4.10, 0.5, 0.0)), None);
+        // Load step from 0.5 A to 1 A
+        assert_eq!(test.push(sample(60, 4.05, 1.0, 0.0125)), None);
+        assert_eq!(test.push(sample(120, 3.90, 1.0, 0.0292)), None);
+        // A single dip under the cutoff doesn't end the test
+        assert_eq!(test.push(sample(180, 2.95, 1.0, 0.0458)), None);
+        assert_eq!(test.push(sample(240, 3.10, 1.0, 0.0625)), None);
+        assert_eq!(test.push(sample(300, 2.98, 1.0, 0.0792)), None);
+        assert_eq!(test.push(sample(360, 2.96, 1.0, 0.0958)), None);
+        assert_eq!(
+            test.push(sample(420, 2.90, 1.0, 0.1125)),
+            Some(StopReason::Cutoff)
+        );
+        assert_eq!(
+            test.push(sample(480, 2.80, 1.0, 0.13)),
+            Some(StopReason::Cutoff)
+        );
+        assert_eq!(test.samples().len(), 8);
+
+        let report = test.report();
+        assert_eq!(report.duration, Duration::from_secs(420));
+        assert_eq!(report.start_voltage, Some(4.10));
+        assert_eq!(report.min_voltage, Some(2.90));
+        assert!((report.capacity - 112.5).abs() < 1e-9);
+        assert_eq!(report.load_steps, 1);
+        assert!((report.internal_resistance.unwrap() - 0.1).abs() < 1e-9);
+        assert_eq!(report.stopped, Some(StopReason::Cutoff));
+        assert!(report.to_string().contains("Capacity:    112.5 mAh"));
+
+        let mut csv = Vec::new();
+        test.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(csv.lines().count(), 9);
+        assert!(
+            csv.lines()
+                .nth(1)
+                .unwrap()
+                .starts_with("1970-01-01T00:00:00+00:00,4.1,0.5,")
+        );
+    }
+
+    #[test]
+    fn test_alternating_meters() {
+        let at = |ms| DateTime::UNIX_EPOCH + TimeDelta::milliseconds(ms);
+        let volts = |text, ms| TimedMeasurement {
+            timestamp: at(ms),
+            ..reading(text, &[DisplayIcon::Volt, DisplayIcon::DC])
+        };
+        let amps = |text, ms| TimedMeasurement {
+            timestamp: at(ms),
+            ..reading(text, &[DisplayIcon::Ampere, DisplayIcon::DC])
+        };
+
+        let mut meter = PowerMeter::new();
+        let mut test = BatteryTest::new();
+        let readings = [
+            volts("4.100", 0),
+            amps("0.500", 100),
+            volts("4.100", 500),
+            // Load step from 0.5 A to 1 A, the voltage reading is still the old one
+            amps("1.000", 600),
+            volts("4.050", 1000),
+            amps("1.000", 1100),
+            volts("4.050", 1500),
+        ];
+        for reading in &readings {
+            if let Some(sample) = meter.push(reading) {
+                test.push(sample);
+            }
+        }
+
+        let report = test.report();
+        assert_eq!(report.load_steps, 1);
+        assert!((report.internal_resistance.unwrap() - 0.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_duration() {
+        let mut test = BatteryTest::new().max_duration(Duration::from_secs(60));
+        assert_eq!(test.push(sample(0, 1.2, -0.1, 0.0)), None);
+        assert_eq!(
+            test.push(sample(60, 1.3, -0.1, -0.0017)),
+            Some(StopReason::Duration)
+        );
+
+        let mut test = BatteryTest::new();
+        test.push(sample(0, 1.2, 0.1, 0.0));
+        test.stop();
+        assert_eq!(test.report().stopped, Some(StopReason::Interrupted));
+        assert_eq!(test.report().internal_resistance, None);
+    }
+}
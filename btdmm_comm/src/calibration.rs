@@ -0,0 +1,367 @@
+//! Corrections for meters reading off by a known amount.
+//!
+//! Calibrations can be read from a text file listing the corrections of each
+//! meter, by Bluetooth address; blank lines and lines starting with `#` are
+//! ignored:
+//!
+//! ```text
+//! device 12:34:56:78:9A:BC
+//! dc-volts gain 1.0012 offset -2m
+//! dc-volts 6.000V offset -1.5m
+//! ohms 600.0Ω offset -0.35
+//!
+//! # Any other meter
+//! device *
+//! dc-volts offset -1m
+//! ```
+//!
+//! A `device <address>` line starts the profile of a meter, `device *` the
+//! one used for meters without a profile of their own. Each correction is
+//! `<mode> [range] [gain <factor>] [offset <value>]`, where modes are named as
+//! in scenarios (`dc-volts`, `ohms`...), the range is written as its full
+//! scale display (`6.000V`, `60.00mV`, `6.000kΩ`...) and the offset is in the
+//! base unit of the mode (V, Ω, °C...), with the `n`, `u`, `m`, `k` and `M`
+//! suffixes. The correction of the range of a reading takes precedence over the
+//! one of its mode; the corrected value is `gain * raw + offset`, computed in
+//! the base unit, so a temperature shown in °F is corrected in °C.
+
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use crate::DmmError;
+use crate::parser::{Measurement, Mode};
+use crate::timed::TimedMeasurement;
+use crate::units::parse_quantity;
+
+/// Range of a meter, told apart by the unit and the decimals it displays.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MeterRange {
+    pub unit: String,
+    pub decimals: u32,
+}
+
+impl MeterRange {
+    /// Returns the range shown by the meter, if the display has a unit.
+    pub fn of(measurement: &Measurement) -> Option<MeterRange> {
+        Some(MeterRange {
+            unit: measurement.value_unit.clone()?,
+            decimals: measurement.decimals(),
+        })
+    }
+
+    /// Parses the full scale display of the range, e.g. `6.000V` or `600.0Ω`.
+    fn parse(text: &str) -> Option<MeterRange> {
+        let split = text.find(|c: char| !c.is_ascii_digit() && c != '.')?;
+        let (number, unit) = text.split_at(split);
+        number.parse::<f64>().ok()?;
+        let decimals = number.split_once('.').map_or(0, |(_, d)| d.len() as u32);
+        let unit = unit.replace("ohm", "Ω").replacen('u', "μ", 1);
+        Some(MeterRange { unit, decimals })
+    }
+}
+
+/// Correction of the readings of one mode, or of one range of it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Correction {
+    pub mode: Mode,
+    /// The range the correction applies to, or `None` for the whole mode.
+    pub range: Option<MeterRange>,
+    pub gain: f64,
+    /// In the base unit of the mode.
+    pub offset: f64,
+}
+
+impl Correction {
+    fn matches(&self, mode: Mode, range: &MeterRange) -> bool {
+        self.mode == mode && self.range.as_ref().is_none_or(|r| r == range)
+    }
+}
+
+/// Corrections of one meter.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CalibrationProfile {
+    pub corrections: Vec<Correction>,
+}
+
+impl CalibrationProfile {
+    pub fn new(corrections: Vec<Correction>) -> CalibrationProfile {
+        CalibrationProfile { corrections }
+    }
+
+    /// Corrects the value of the reading, keeping the reading as decoded in
+    /// its `raw` field. Returns whether a correction applied.
+    ///
+    /// The corrected value is shown at the resolution of the display, with
+    /// fewer decimals when it no longer fits in the four digits (as from
+    /// 9.999 to 10.01). Overloads, readings without a known mode and readings
+    /// whose corrected value can't be shown are left as they are.
+    pub fn apply(&self, reading: &mut TimedMeasurement) -> bool {
+        let measurement = &reading.measurement;
+        let (Some(mode), Some(range), Some(value)) = (
+            measurement.mode(),
+            MeterRange::of(measurement),
+            measurement.value(),
+        ) else {
+            return false;
+        };
+
+        let matching = || self.corrections.iter().filter(|c| c.matches(mode, &range));
+        let Some(correction) = matching()
+            .find(|c| c.range.is_some())
+            .or_else(|| matching().next())
+        else {
+            return false;
+        };
+
+        // In the base unit, so that the offset of a temperature is in °C
+        // whatever the meter displays
+        let corrected = measurement
+            .to_displayed(correction.gain * measurement.to_base(value) + correction.offset);
+        let Some(corrected) = (0..=range.decimals as usize).rev().find_map(|decimals| {
+            let text = display_text(corrected, decimals);
+            let frame =
+                Measurement::encode(measurement.dmm_id, &text, &measurement.displayed_icons)
+                    .ok()?;
+            Measurement::from_bytes(&frame).ok()
+        }) else {
+            return false;
+        };
+
+        reading.raw = Some(std::mem::replace(&mut reading.measurement, corrected));
+        true
+    }
+}
+
+/// Formats `value` as shown by the four digits of the display with
+/// `decimals` decimals, e.g. "05.00" or "-1.250".
+fn display_text(value: f64, decimals: usize) -> String {
+    let width = if decimals > 0 { 5 } else { 4 };
+    let digits = format!("{:0width$.decimals$}", value.abs());
+    if value < 0.0 {
+        format!("-{}", digits)
+    } else {
+        digits
+    }
+}
+
+/// Calibration profiles of a set of meters.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Calibrations {
+    /// Profiles by Bluetooth address, `None` standing for any other meter.
+    profiles: Vec<(Option<String>, CalibrationProfile)>,
+}
+
+impl Calibrations {
+    /// Parses the calibration file format described in the module
+    /// documentation.
+    pub fn parse(text: &str) -> Result<Calibrations, DmmError> {
+        let mut profiles: Vec<(Option<String>, CalibrationProfile)> = Vec::new();
+
+        for (lineno, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |what: String| {
+                DmmError::InvalidCalibration(format!("line {}: {}", lineno + 1, what))
+            };
+
+            if let Some(address) = line.strip_prefix("device ") {
+                let address = match address.trim() {
+                    "*" => None,
+                    address => Some(address.to_ascii_uppercase()),
+                };
+                if profiles.iter().any(|(a, _)| *a == address) {
+                    return Err(invalid("device listed twice".to_string()));
+                }
+                profiles.push((address, CalibrationProfile::default()));
+                continue;
+            }
+
+            let correction = parse_correction(line).map_err(invalid)?;
+            let (_, profile) = profiles
+                .last_mut()
+                .ok_or_else(|| invalid("expected \"device <address>\" first".to_string()))?;
+            profile.corrections.push(correction);
+        }
+
+        Ok(Calibrations { profiles })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Calibrations, Box<dyn Error>> {
+        Ok(Calibrations::parse(&fs::read_to_string(path)?)?)
+    }
+
+    /// Returns the profile of the meter with the given address, falling back
+    /// to the `device *` one. Meters without an address, such as simulated
+    /// ones, only get the latter.
+    pub fn for_device(&self, address: Option<&str>) -> Option<&CalibrationProfile> {
+        let address = address.map(str::to_ascii_uppercase);
+        self.profiles
+            .iter()
+            .find(|(a, _)| a.is_some() && *a == address)
+            .or_else(|| self.profiles.iter().find(|(a, _)| a.is_none()))
+            .map(|(_, profile)| profile)
+    }
+}
+
+fn parse_correction(line: &str) -> Result<Correction, String> {
+    let mut tokens = line.split_whitespace().peekable();
+    let mode = tokens
+        .next()
+        .unwrap_or_default()
+        .parse()
+        .map_err(|e: DmmError| e.to_string())?;
+    let range = match tokens.peek() {
+        Some(&token) if token != "gain" && token != "offset" => {
+            tokens.next();
+            Some(MeterRange::parse(token).ok_or_else(|| format!("invalid range {:?}", token))?)
+        }
+        _ => None,
+    };
+
+    let mut gain = 1.0;
+    let mut offset = 0.0;
+    while let Some(option) = tokens.next() {
+        let value = tokens.next().and_then(parse_quantity);
+        match option {
+            "gain" => gain = value.ok_or("expected a gain")?,
+            "offset" => offset = value.ok_or("expected an offset")?,
+            other => return Err(format!("unknown option {:?}", other)),
+        }
+    }
+
+    Ok(Correction {
+        mode,
+        range,
+        gain,
+        offset,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::DisplayIcon;
+    use crate::timed::reading;
+
+    const CALIBRATIONS: &str = "
+        # Bench meter
+        device 12:34:56:78:9a:bc
+        dc-volts gain 1.001 offset -2m
+        dc-volts 60.00mV offset -150u
+        ohms 600.0ohm offset -0.4
+
+        device *
+        dc-volts offset 10m
+    ";
+
+    #[test]
+    fn test_parse() {
+        let calibrations = Calibrations::parse(CALIBRATIONS).unwrap();
+        let profile = calibrations.for_device(Some("12:34:56:78:9A:BC")).unwrap();
+        assert_eq!(profile.corrections.len(), 3);
+        assert_eq!(
+            profile.corrections[2],
+            Correction {
+                mode: Mode::Resistance,
+                range: Some(MeterRange {
+                    unit: "Ω".to_string(),
+                    decimals: 1
+                }),
+                gain: 1.0,
+                offset: -0.4,
+            }
+        );
+        let other = calibrations.for_device(Some("AA:BB:CC:DD:EE:FF")).unwrap();
+        assert_eq!(other.corrections[0].offset, 0.01);
+        assert_eq!(calibrations.for_device(None), Some(other));
+
+        let error = Calibrations::parse("dc-volts offset 1m").unwrap_err();
+        assert!(error.to_string().contains("line 1"));
+        assert!(Calibrations::parse("device *\nohms gain").is_err());
+        assert!(Calibrations::parse("device *\nohms 6.000 offset 1").is_err());
+        assert!(Calibrations::parse("device *\nwatts offset 1").is_err());
+    }
+
+    #[test]
+    fn test_apply() {
+        let calibrations = Calibrations::parse(CALIBRATIONS).unwrap();
+        let profile = calibrations.for_device(Some("12:34:56:78:9a:bc")).unwrap();
+
+        // 1.001 * 5 V - 2 mV
+        let mut volts = reading("5.000", &[DisplayIcon::Volt, DisplayIcon::DC]);
+        assert!(profile.apply(&mut volts));
+        assert_eq!(volts.measurement.value(), Some(5.003));
+        assert_eq!(volts.raw.as_ref().unwrap().value(), Some(5.0));
+        assert_eq!(volts.measurement.value_unit.as_deref(), Some("V"));
+
+        // The correction of the range wins over the one of the mode
+        let mut millivolts = reading("12.00", &[DisplayIcon::MilliVolt, DisplayIcon::DC]);
+        assert!(profile.apply(&mut millivolts));
+        assert_eq!(millivolts.measurement.value(), Some(11.85));
+
+        let mut ohms = reading("100.0", &[DisplayIcon::Ohm]);
+        assert!(profile.apply(&mut ohms));
+        assert_eq!(ohms.measurement.value(), Some(99.6));
+        // Other ranges of the mode aren't corrected
+        let mut kilohms = reading("1.000", &[DisplayIcon::KiloOhm]);
+        assert!(!profile.apply(&mut kilohms));
+        assert_eq!(kilohms.raw, None);
+
+        let mut overload = reading(" .0L ", &[DisplayIcon::Volt, DisplayIcon::DC]);
+        assert!(!profile.apply(&mut overload));
+        let mut amps = reading("1.000", &[DisplayIcon::Ampere, DisplayIcon::DC]);
+        assert!(!profile.apply(&mut amps));
+    }
+
+    #[test]
+    fn test_apply_display() {
+        let calibrations = Calibrations::parse(CALIBRATIONS).unwrap();
+        let profile = calibrations.for_device(Some("12:34:56:78:9a:bc")).unwrap();
+
+        // The display shows the corrected value too
+        let mut volts = reading("5.000", &[DisplayIcon::Volt, DisplayIcon::DC]);
+        profile.apply(&mut volts);
+        let expected = reading("5.003", &[DisplayIcon::Volt, DisplayIcon::DC]);
+        assert_eq!(volts.measurement, expected.measurement);
+
+        // 1.001 * 9.999 V - 2 mV doesn't fit in 4 digits with 3 decimals
+        let mut volts = reading("9.999", &[DisplayIcon::Volt, DisplayIcon::DC]);
+        assert!(profile.apply(&mut volts));
+        let expected = reading("10.01", &[DisplayIcon::Volt, DisplayIcon::DC]);
+        assert_eq!(volts.measurement, expected.measurement);
+        assert_eq!(volts.measurement.resolution(), Some(0.01));
+
+        // Negative values keep their sign, small ones their leading zero
+        let other = calibrations.for_device(None).unwrap();
+        let mut volts = reading("-0.020", &[DisplayIcon::Volt, DisplayIcon::DC]);
+        assert!(other.apply(&mut volts));
+        assert_eq!(volts.measurement.value(), Some(-0.01));
+
+        // Left as it is when the corrected value can't be shown
+        let mut volts = reading("9999", &[DisplayIcon::Volt, DisplayIcon::DC]);
+        assert!(!profile.apply(&mut volts));
+        assert_eq!(volts.measurement.value(), Some(9999.0));
+        assert_eq!(volts.raw, None);
+    }
+
+    #[test]
+    fn test_apply_fahrenheit() {
+        let calibrations =
+            Calibrations::parse("device *\ntemperature gain 1.01 offset -2").unwrap();
+        let profile = calibrations.for_device(None).unwrap();
+
+        // 1.01 * 100 °C - 2 °C
+        let mut celsius = reading("100.0", &[DisplayIcon::DegC]);
+        assert!(profile.apply(&mut celsius));
+        assert_eq!(celsius.measurement.value(), Some(99.0));
+
+        // The same correction, applied to 100 °C shown in °F
+        let mut fahrenheit = reading("212.0", &[DisplayIcon::DegF]);
+        assert!(profile.apply(&mut fahrenheit));
+        assert_eq!(fahrenheit.measurement.value(), Some(210.2));
+        assert_eq!(fahrenheit.measurement.value_unit.as_deref(), Some("°F"));
+    }
+}
@@ -1,64 +1,238 @@
 use std::error::Error;
+use std::pin::Pin;
+use std::sync::Mutex;
 use std::time::Duration;
 
-use async_recursion::async_recursion;
+use async_std::channel::{self, Receiver};
 use async_std::future;
+use async_std::task::{self, JoinHandle};
 use btleplug::api::{Central, Peripheral, ScanFilter};
 use btleplug::platform::Adapter;
-use futures::stream::StreamExt;
+use futures::stream::{self, AbortHandle, Stream, StreamExt};
 use uuid::Uuid;
 
 use crate::DmmError;
+use crate::calibration::CalibrationProfile;
+use crate::link::{LinkStats, LinkTracker};
 use crate::parser::Measurement;
+use crate::profile::{Button, Command, ModelProfile, profile_for};
+use crate::timed::{Frame, TimedMeasurement};
+use crate::transport::Transport;
 
 const DMM_NAME: &str = "Bluetooth DMM";
-const DMM_CHARACTERISTIC_UUID: Uuid = Uuid::from_u128(0x0000fff4_0000_1000_8000_00805f9b34fb);
+pub(crate) const DMM_CHARACTERISTIC_UUID: Uuid =
+    Uuid::from_u128(0x0000fff4_0000_1000_8000_00805f9b34fb);
 
+pub type MeasurementStream<'a> =
+    Pin<Box<dyn Stream<Item = Result<TimedMeasurement, Box<dyn Error>>> + 'a>>;
+
+#[derive(Debug)]
+struct Session {
+    frames: Receiver<Frame>,
+    /// Task stamping the notifications as soon as they are received.
+    receiver: JoinHandle<()>,
+}
+
+/// Connection with a meter.
+///
+/// Dropping a connected device disconnects it in the background; call
+/// `disconnect` to know when the link has actually been released.
 #[derive(Debug)]
-pub struct DmmDevice<P: Peripheral> {
+pub struct DmmDevice<P: Transport> {
     device: P,
+    profile: Mutex<Option<&'static ModelProfile>>,
+    calibration: Option<CalibrationProfile>,
+    link: Mutex<LinkTracker>,
+    session: Mutex<Option<Session>>,
 }
 
-impl<P: Peripheral> DmmDevice<P> {
+impl<P: Transport> DmmDevice<P> {
     pub fn new(device: P) -> DmmDevice<P> {
-        DmmDevice { device }
+        DmmDevice {
+            device,
+            profile: Mutex::new(None),
+            calibration: None,
+            link: Mutex::new(LinkTracker::default()),
+            session: Mutex::new(None),
+        }
+    }
+
+    /// Uses the given profile instead of detecting it from the first measurement.
+    pub fn with_profile(self, profile: &'static ModelProfile) -> DmmDevice<P> {
+        *self.profile.lock().unwrap() = Some(profile);
+        self
+    }
+
+    /// Corrects the measurements with the given calibration profile.
+    pub fn with_calibration(mut self, calibration: CalibrationProfile) -> DmmDevice<P> {
+        self.calibration = Some(calibration);
+        self
     }
 
     pub fn device(&self) -> &P {
         &self.device
     }
 
+    /// Returns the model profile, known once a measurement has been received.
+    pub fn profile(&self) -> Option<&'static ModelProfile> {
+        *self.profile.lock().unwrap()
+    }
+
+    /// Connects to the meter, starting a new set of link statistics and
+    /// sequence numbers.
     pub async fn connect(&self) -> Result<(), Box<dyn Error>> {
         self.device.connect().await?;
-        self.device.discover_services().await?;
+        let mut notifications = self.device.notifications().await?;
 
-        let chars = self.device.characteristics();
-        let char = chars
-            .iter()
-            .find(|c| c.uuid == DMM_CHARACTERISTIC_UUID)
-            .expect("Unable to find the DMM characteristic");
-        self.device.subscribe(char).await?;
+        let (sender, frames) = channel::unbounded();
+        let receiver = task::spawn(async move {
+            let mut sequence = 0;
+            while let Some(notification) = notifications.next().await {
+                if notification.uuid != DMM_CHARACTERISTIC_UUID {
+                    continue;
+                }
+                let frame = Frame::new(notification.value, sequence);
+                sequence += 1;
+                if sender.send(frame).await.is_err() {
+                    break;
+                }
+            }
+        });
 
+        *self.link.lock().unwrap() = LinkTracker::default();
+        let previous = self
+            .session
+            .lock()
+            .unwrap()
+            .replace(Session { frames, receiver });
+        if let Some(previous) = previous {
+            previous.receiver.cancel().await;
+        }
         Ok(())
     }
 
     pub async fn disconnect(&mut self) -> Result<(), Box<dyn Error>> {
-        self.device.disconnect().await?;
-        Ok(())
+        let session = self.session.lock().unwrap().take();
+        if let Some(session) = session {
+            session.receiver.cancel().await;
+        }
+        self.device.disconnect().await
+    }
+
+    /// Waits for the next raw (still scrambled) frame sent by the meter.
+    pub async fn next_frame(&self, timeout: Duration) -> Result<Frame, Box<dyn Error>> {
+        let frames = self
+            .session
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|s| s.frames.clone())
+            .ok_or(DmmError::NotConnected)?;
+
+        match future::timeout(timeout, frames.recv()).await {
+            Ok(Ok(frame)) => {
+                self.link.lock().unwrap().frame_received(frame.received);
+                Ok(frame)
+            }
+            Ok(Err(_)) => Err(DmmError::DeviceDisconnected.into()),
+            Err(err) => {
+                self.link.lock().unwrap().timeout();
+                Err(err.into())
+            }
+        }
+    }
+
+    /// Waits for the next measurement, along with the time it was received.
+    pub async fn next_timed_event(
+        &self,
+        timeout: Duration,
+    ) -> Result<TimedMeasurement, Box<dyn Error>> {
+        let frame = self.next_frame(timeout).await?;
+        let mut timed = TimedMeasurement::decode(&frame).inspect_err(|_| {
+            self.link.lock().unwrap().invalid_frame();
+        })?;
+        self.profile
+            .lock()
+            .unwrap()
+            .get_or_insert_with(|| profile_for(timed.measurement.dmm_id));
+        if let Some(calibration) = &self.calibration {
+            calibration.apply(&mut timed);
+        }
+        Ok(timed)
     }
 
-    #[async_recursion]
     pub async fn next_event(&self, timeout: Duration) -> Result<Measurement, Box<dyn Error>> {
-        let mut notifications = self.device.notifications().await?;
-        let data = future::timeout(timeout, async move { notifications.next().await })
-            .await?
-            .ok_or(DmmError::DeviceDisconnected)?;
+        Ok(self.next_timed_event(timeout).await?.measurement)
+    }
+
+    /// Returns the stream of measurements and a handle to cancel it.
+    ///
+    /// Errors such as timeouts are yielded without ending the stream; it ends
+    /// after the meter disconnects or when it is aborted through the handle.
+    pub fn measurements(&self, timeout: Duration) -> (MeasurementStream<'_>, AbortHandle) {
+        let measurements = stream::unfold(false, move |ended| async move {
+            if ended {
+                return None;
+            }
+            let result = self.next_timed_event(timeout).await;
+            let ended = matches!(
+                result.as_ref().err().and_then(|e| e.downcast_ref()),
+                Some(DmmError::DeviceDisconnected | DmmError::NotConnected)
+            );
+            Some((result, ended))
+        });
+        let (measurements, handle) = stream::abortable(measurements);
+        (Box::pin(measurements), handle)
+    }
 
-        if data.uuid != DMM_CHARACTERISTIC_UUID {
-            return self.next_event(timeout).await;
+    pub fn link_stats(&self) -> LinkStats {
+        self.link.lock().unwrap().snapshot()
+    }
+
+    /// Reads the signal strength and adds it to the link statistics.
+    pub async fn sample_rssi(&self) -> Result<Option<i16>, Box<dyn Error>> {
+        let rssi = self.device.rssi().await?;
+        if let Some(rssi) = rssi {
+            self.link.lock().unwrap().rssi(rssi);
         }
+        Ok(rssi)
+    }
+
+    /// Sends a remote control command, failing with `DmmError::Unsupported` if
+    /// the meter's profile doesn't know it.
+    pub async fn send(&self, command: Command) -> Result<(), Box<dyn Error>> {
+        let profile = self.profile().ok_or(DmmError::Unsupported)?;
+        let characteristic = profile
+            .command_characteristic
+            .ok_or(DmmError::Unsupported)?;
+        let data = (profile.encode_command)(command).ok_or(DmmError::Unsupported)?;
+        self.device.write(characteristic, &data).await
+    }
+
+    pub async fn press(&self, button: Button) -> Result<(), Box<dyn Error>> {
+        self.send(Command::Press(button)).await
+    }
+
+    pub async fn set_range(&self, range: u8) -> Result<(), Box<dyn Error>> {
+        self.send(Command::SetRange(range)).await
+    }
 
-        Ok(Measurement::from_bytes(data.value.as_slice().try_into()?)?)
+    pub async fn set_auto_range(&self) -> Result<(), Box<dyn Error>> {
+        self.send(Command::AutoRange).await
+    }
+}
+
+impl<P: Transport> Drop for DmmDevice<P> {
+    fn drop(&mut self) {
+        let session = self.session.get_mut().unwrap().take();
+        if let Some(session) = session {
+            let device = self.device.clone();
+            task::spawn(async move {
+                session.receiver.cancel().await;
+                // Nobody is left to report the error to
+                let _ = device.disconnect().await;
+            });
+        }
     }
 }
 
@@ -86,3 +260,225 @@ pub async fn scan_for_dmm(
 
     Err(DmmError::DeviceNotFound.into())
 }
+
+/// Finds all the meters in range, for setups using several of them.
+pub async fn scan_for_dmms(
+    adapter: Adapter,
+) -> Result<Vec<DmmDevice<btleplug::platform::Peripheral>>, Box<dyn Error>> {
+    let filter = ScanFilter {
+        services: vec![DMM_CHARACTERISTIC_UUID],
+    };
+
+    adapter.start_scan(filter).await?;
+
+    let mut devices = Vec::new();
+    for p in adapter.peripherals().await? {
+        let name = p
+            .properties()
+            .await?
+            .and_then(|properties| properties.local_name)
+            .unwrap_or_default();
+        if name == DMM_NAME {
+            devices.push(DmmDevice::new(p));
+        }
+    }
+    adapter.stop_scan().await?;
+
+    if devices.is_empty() {
+        return Err(DmmError::DeviceNotFound.into());
+    }
+    Ok(devices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockTransport;
+    use crate::parser::DisplayIcon;
+
+    const COMMAND_UUID: Uuid = Uuid::from_u128(0x0000fff3_0000_1000_8000_00805f9b34fb);
+
+    static REMOTE_PROFILE: ModelProfile = ModelProfile {
+        name: "Remote controlled DMM",
+        dmm_id: 3,
+        command_characteristic: Some(COMMAND_UUID),
+        encode_command: |command| match command {
+            Command::Press(Button::Hold) => Some(vec![0x01]),
+            Command::SetRange(range) => Some(vec![0x02, range]),
+            _ => None,
+        },
+        accuracy: &[],
+    };
+
+    fn is_unsupported(err: Box<dyn Error>) -> bool {
+        matches!(err.downcast_ref::<DmmError>(), Some(DmmError::Unsupported))
+    }
+
+    #[test]
+    fn test_commands() {
+        let transport = MockTransport::new();
+        let dmm = DmmDevice::new(transport.clone()).with_profile(&REMOTE_PROFILE);
+
+        async_std::task::block_on(async {
+            dmm.connect().await.unwrap();
+            dmm.press(Button::Hold).await.unwrap();
+            dmm.set_range(2).await.unwrap();
+            assert!(is_unsupported(dmm.press(Button::Select).await.unwrap_err()));
+            assert!(is_unsupported(dmm.set_auto_range().await.unwrap_err()));
+        });
+
+        assert_eq!(
+            transport.written(),
+            vec![(COMMAND_UUID, vec![0x01]), (COMMAND_UUID, vec![0x02, 2])]
+        );
+    }
+
+    #[test]
+    fn test_commands_unsupported() {
+        let transport = MockTransport::new();
+        let dmm = DmmDevice::new(transport.clone());
+        let frame = Measurement::encode(3, "1.234", &[DisplayIcon::Volt]).unwrap();
+        transport.push_frame(&frame);
+
+        async_std::task::block_on(async {
+            dmm.connect().await.unwrap();
+            // The model is unknown until the first frame arrives
+            assert!(is_unsupported(dmm.press(Button::Hold).await.unwrap_err()));
+
+            dmm.next_event(Duration::from_secs(1)).await.unwrap();
+            assert_eq!(dmm.profile().unwrap().dmm_id, 3);
+            assert!(is_unsupported(dmm.press(Button::Hold).await.unwrap_err()));
+        });
+
+        assert!(transport.written().is_empty());
+    }
+
+    #[test]
+    fn test_link_stats() {
+        let transport = MockTransport::new();
+        let dmm = DmmDevice::new(transport.clone());
+        let frame = Measurement::encode(3, "1.234", &[DisplayIcon::Volt]).unwrap();
+        transport.push_frame(&frame);
+        transport.push_frame(&frame[..5]);
+        let timeout = Duration::from_millis(10);
+
+        async_std::task::block_on(async {
+            dmm.connect().await.unwrap();
+            dmm.next_event(timeout).await.unwrap();
+            assert!(dmm.next_event(timeout).await.is_err());
+            assert!(dmm.next_event(timeout).await.is_err());
+            assert_eq!(dmm.sample_rssi().await.unwrap(), None);
+        });
+
+        let stats = dmm.link_stats();
+        assert_eq!(stats.frames, 2);
+        assert_eq!(stats.invalid_frames, 1);
+        assert_eq!(stats.timeouts, 1);
+        assert_eq!(stats.rssi, None);
+    }
+
+    #[test]
+    fn test_timed_events() {
+        let transport = MockTransport::new();
+        let dmm = DmmDevice::new(transport.clone());
+        let timeout = Duration::from_secs(1);
+        let frame = Measurement::encode(3, "1.234", &[DisplayIcon::Volt]).unwrap();
+
+        async_std::task::block_on(async {
+            assert!(dmm.next_timed_event(timeout).await.is_err());
+            dmm.connect().await.unwrap();
+
+            transport.push_frame(&frame);
+            transport.push_notification(COMMAND_UUID, &[0x00]);
+            transport.push_frame(&frame[..5]);
+            transport.push_frame(&frame);
+            // Frames are stamped on reception, not when they are read
+            async_std::task::sleep(Duration::from_millis(20)).await;
+            let before_read = std::time::Instant::now();
+
+            let first = dmm.next_timed_event(timeout).await.unwrap();
+            assert_eq!(first.sequence, 0);
+            assert!(first.received < before_read);
+            assert!(dmm.next_timed_event(timeout).await.is_err());
+            let second = dmm.next_timed_event(timeout).await.unwrap();
+            assert_eq!(second.sequence, 2);
+            assert!(second.received >= first.received);
+            assert!(second.timestamp >= first.timestamp);
+            assert_eq!(second.measurement, first.measurement);
+
+            transport.close();
+            let err = dmm.next_timed_event(timeout).await.unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<DmmError>(),
+                Some(DmmError::DeviceDisconnected)
+            ));
+        });
+    }
+
+    #[test]
+    fn test_measurements_stream() {
+        let transport = MockTransport::new();
+        let dmm = DmmDevice::new(transport.clone());
+        let frame = Measurement::encode(3, "1.234", &[DisplayIcon::Volt]).unwrap();
+
+        async_std::task::block_on(async {
+            dmm.connect().await.unwrap();
+            transport.push_frame(&frame);
+            transport.push_frame(&frame[..5]);
+            transport.push_frame(&frame);
+            transport.close();
+
+            let (measurements, _) = dmm.measurements(Duration::from_secs(1));
+            let results = measurements.collect::<Vec<_>>().await;
+            assert_eq!(results.len(), 4);
+            assert!(results[0].is_ok());
+            assert!(results[1].is_err());
+            assert!(results[2].is_ok());
+            assert!(results[3].is_err());
+        });
+    }
+
+    #[test]
+    fn test_measurements_abort() {
+        let transport = MockTransport::new();
+        let dmm = DmmDevice::new(transport.clone());
+        let frame = Measurement::encode(3, "1.234", &[DisplayIcon::Volt]).unwrap();
+
+        async_std::task::block_on(async {
+            dmm.connect().await.unwrap();
+            transport.push_frame(&frame);
+
+            let (mut measurements, handle) = dmm.measurements(Duration::from_secs(10));
+            assert!(measurements.next().await.unwrap().is_ok());
+
+            // Abort while waiting for a frame that never comes
+            async_std::task::spawn(async move {
+                async_std::task::sleep(Duration::from_millis(10)).await;
+                handle.abort();
+            });
+            let start = std::time::Instant::now();
+            assert!(measurements.next().await.is_none());
+            assert!(start.elapsed() < Duration::from_secs(1));
+        });
+    }
+
+    #[test]
+    fn test_disconnect_on_drop() {
+        let transport = MockTransport::new();
+        let dmm = DmmDevice::new(transport.clone());
+
+        async_std::task::block_on(async {
+            dmm.connect().await.unwrap();
+            assert!(transport.is_connected());
+            drop(dmm);
+
+            for _ in 0..100 {
+                if !transport.is_connected() {
+                    break;
+                }
+                async_std::task::sleep(Duration::from_millis(10)).await;
+            }
+            assert!(!transport.is_connected());
+        });
+    }
+}
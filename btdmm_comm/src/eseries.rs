@@ -0,0 +1,301 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::DmmError;
+use crate::parser::Mode;
+use crate::timed::TimedMeasurement;
+use crate::units::{format_si, scale};
+
+static E12: [u16; 12] = [10, 12, 15, 18, 22, 27, 33, 39, 47, 56, 68, 82];
+
+static E24: [u16; 24] = [
+    10, 11, 12, 13, 15, 16, 18, 20, 22, 24, 27, 30, 33, 36, 39, 43, 47, 51, 56, 62, 68, 75, 82, 91,
+];
+
+static E96: [u16; 96] = [
+    100, 102, 105, 107, 110, 113, 115, 118, 121, 124, 127, 130, 133, 137, 140, 143, 147, 150, 154,
+    158, 162, 165, 169, 174, 178, 182, 187, 191, 196, 200, 205, 210, 215, 221, 226, 232, 237, 243,
+    249, 255, 261, 267, 274, 280, 287, 294, 301, 309, 316, 324, 332, 340, 348, 357, 365, 374, 383,
+    392, 402, 412, 422, 432, 442, 453, 464, 475, 487, 499, 511, 523, 536, 549, 562, 576, 590, 604,
+    619, 634, 649, 665, 681, 698, 715, 732, 750, 768, 787, 806, 825, 845, 866, 887, 909, 931, 953,
+    976,
+];
+
+/// IEC 60063 series of preferred values for resistors and capacitors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ESeries {
+    E12,
+    E24,
+    E96,
+}
+
+impl ESeries {
+    /// Values of one decade, with the number of significant digits.
+    fn table(&self) -> (&'static [u16], i32) {
+        match self {
+            ESeries::E12 => (&E12, 2),
+            ESeries::E24 => (&E24, 2),
+            ESeries::E96 => (&E96, 3),
+        }
+    }
+
+    /// Usual tolerance of the parts of the series, in percent.
+    pub fn tolerance(&self) -> f64 {
+        match self {
+            ESeries::E12 => 10.0,
+            ESeries::E24 => 5.0,
+            ESeries::E96 => 1.0,
+        }
+    }
+
+    /// Returns the value of the series closest to `value`, which must be
+    /// positive.
+    pub fn nearest(&self, value: f64) -> Option<f64> {
+        if !(value.is_finite() && value > 0.0) {
+            return None;
+        }
+        let (table, digits) = self.table();
+        let exponent = value.log10().floor() as i32 - (digits - 1);
+        let mantissa = scale(value, -exponent);
+
+        // The first value of the next decade may be the closest
+        let next_decade = 10u16.pow(digits as u32);
+        table
+            .iter()
+            .copied()
+            .chain([next_decade])
+            .map(f64::from)
+            .min_by(|a, b| {
+                (mantissa / a)
+                    .ln()
+                    .abs()
+                    .total_cmp(&(mantissa / b).ln().abs())
+            })
+            .map(|m| scale(m, exponent))
+    }
+}
+
+impl FromStr for ESeries {
+    type Err = DmmError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "E12" => Ok(ESeries::E12),
+            "E24" => Ok(ESeries::E24),
+            "E96" => Ok(ESeries::E96),
+            _ => Err(DmmError::UnknownSeries(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for ESeries {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// Outcome of checking a component against the series.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BinResult {
+    pub mode: Mode,
+    /// Measured value, in ohms or farads.
+    pub value: f64,
+    /// Nearest value of the series, in ohms or farads.
+    pub nominal: f64,
+    /// Relative deviation from the nominal value, in percent.
+    pub deviation: f64,
+    pub pass: bool,
+}
+
+impl fmt::Display for BinResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unit = self.mode.unit();
+        write!(
+            f,
+            "{}: {} ({:+.2}%) {}",
+            format_si(self.nominal, unit),
+            format_si(self.value, unit),
+            self.deviation,
+            if self.pass { "pass" } else { "FAIL" }
+        )
+    }
+}
+
+/// Count of the components sorted into one bin.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BinTally {
+    pub mode: Mode,
+    pub nominal: f64,
+    pub passed: usize,
+    pub failed: usize,
+}
+
+impl fmt::Display for BinTally {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} passed, {} failed",
+            format_si(self.nominal, self.mode.unit()),
+            self.passed,
+            self.failed
+        )
+    }
+}
+
+/// Sorts resistors and capacitors into bins of the nearest preferred value,
+/// checking they are within tolerance of it.
+///
+/// Feed it settled readings (see `SettleDetector`), one per component.
+#[derive(Debug, Clone)]
+pub struct ComponentSorter {
+    series: ESeries,
+    tolerance: f64,
+    tally: Vec<BinTally>,
+}
+
+impl ComponentSorter {
+    /// Sorts into `series` with the usual tolerance of its parts.
+    pub fn new(series: ESeries) -> ComponentSorter {
+        ComponentSorter {
+            series,
+            tolerance: series.tolerance(),
+            tally: Vec::new(),
+        }
+    }
+
+    /// Accepted deviation from the nominal value, in percent.
+    pub fn tolerance(mut self, percent: f64) -> Self {
+        self.tolerance = percent;
+        self
+    }
+
+    /// Checks the component being measured, counting it in the tally. Returns
+    /// `None` for readings that aren't a resistance or a capacitance.
+    pub fn check(&mut self, reading: &TimedMeasurement) -> Option<BinResult> {
+        let measurement = &reading.measurement;
+        let mode = measurement
+            .mode()
+            .filter(|mode| matches!(mode, Mode::Resistance | Mode::Capacitance))?;
+        let value = measurement.base_value()?;
+        let nominal = self.series.nearest(value)?;
+
+        let deviation = (value - nominal) / nominal * 100.0;
+        let pass = deviation.abs() <= self.tolerance;
+
+        let bin = match self
+            .tally
+            .iter_mut()
+            .position(|bin| bin.mode == mode && bin.nominal == nominal)
+        {
+            Some(i) => &mut self.tally[i],
+            None => {
+                self.tally.push(BinTally {
+                    mode,
+                    nominal,
+                    passed: 0,
+                    failed: 0,
+                });
+                self.tally.last_mut().unwrap()
+            }
+        };
+        if pass {
+            bin.passed += 1;
+        } else {
+            bin.failed += 1;
+        }
+
+        Some(BinResult {
+            mode,
+            value,
+            nominal,
+            deviation,
+            pass,
+        })
+    }
+
+    /// Bins with at least one component, by mode then by value.
+    pub fn tally(&self) -> Vec<BinTally> {
+        let mut tally = self.tally.clone();
+        tally.sort_by(|a, b| {
+            (a.mode == Mode::Capacitance)
+                .cmp(&(b.mode == Mode::Capacitance))
+                .then(a.nominal.total_cmp(&b.nominal))
+        });
+        tally
+    }
+
+    pub fn reset(&mut self) {
+        self.tally.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::DisplayIcon;
+    use crate::timed::reading;
+
+    #[test]
+    fn test_nearest() {
+        assert_eq!(E96.len(), 96);
+        assert_eq!(ESeries::E12.nearest(4.6e3), Some(4.7e3));
+        assert_eq!(ESeries::E24.nearest(5.0), Some(5.1));
+        assert_eq!(ESeries::E24.nearest(9.7e-9), Some(1e-8));
+        assert_eq!(ESeries::E96.nearest(10.1e3), Some(10.2e3));
+        assert_eq!(ESeries::E96.nearest(0.0), None);
+        assert_eq!("e24".parse::<ESeries>().unwrap(), ESeries::E24);
+        assert!("E6".parse::<ESeries>().is_err());
+    }
+
+    #[test]
+    fn test_sorter() {
+        let mut sorter = ComponentSorter::new(ESeries::E24).tolerance(2.0);
+
+        let result = sorter
+            .check(&reading("4.712", &[DisplayIcon::KiloOhm]))
+            .unwrap();
+        assert_eq!(result.mode, Mode::Resistance);
+        assert_eq!(result.nominal, 4700.0);
+        assert!((result.deviation - 0.2553).abs() < 1e-3);
+        assert!(result.pass);
+        assert_eq!(result.to_string(), "4.7 kΩ: 4.712 kΩ (+0.26%) pass");
+
+        // 4.95 k is closer to 5.1 k, but 2.9% off
+        let result = sorter
+            .check(&reading("4.950", &[DisplayIcon::KiloOhm]))
+            .unwrap();
+        assert_eq!(result.nominal, 5100.0);
+        assert!(!result.pass);
+        let result = sorter
+            .check(&reading("4.870", &[DisplayIcon::KiloOhm]))
+            .unwrap();
+        assert_eq!(result.nominal, 4700.0);
+        assert!(!result.pass);
+
+        let capacitor = [DisplayIcon::Farad, DisplayIcon::NanoFarad];
+        let result = sorter.check(&reading("98.50", &capacitor)).unwrap();
+        assert_eq!(result.mode, Mode::Capacitance);
+        assert!((result.nominal - 100e-9).abs() < 1e-18);
+
+        assert!(
+            sorter
+                .check(&reading("1.000", &[DisplayIcon::Volt]))
+                .is_none()
+        );
+        assert!(
+            sorter
+                .check(&reading(" .0L ", &[DisplayIcon::MegaOhm]))
+                .is_none()
+        );
+
+        let tally = sorter.tally();
+        assert_eq!(tally.len(), 3);
+        assert_eq!(
+            (tally[0].nominal, tally[0].passed, tally[0].failed),
+            (4700.0, 1, 1)
+        );
+        assert_eq!((tally[1].nominal, tally[1].failed), (5100.0, 1));
+        assert_eq!(tally[2].mode, Mode::Capacitance);
+        assert_eq!(tally[2].to_string(), "100 nF: 1 passed, 0 failed");
+    }
+}
@@ -0,0 +1,166 @@
+use std::future;
+use std::time::Duration;
+
+use futures::StreamExt;
+
+use crate::device::MeasurementStream;
+use crate::settle::Tolerance;
+use crate::timed::TimedMeasurement;
+
+/// Drops readings that repeat the last one let through, to keep long logs
+/// small.
+///
+/// A reading passes when the displayed value, the icons (and so the mode and
+/// unit) change. With a deadband, value changes within the tolerance are
+/// ignored; with a heartbeat, a reading passes anyway once the interval has
+/// elapsed since the last one.
+#[derive(Debug, Clone, Default)]
+pub struct ReadingFilter {
+    deadband: Option<Tolerance>,
+    heartbeat: Option<Duration>,
+    last: Option<TimedMeasurement>,
+}
+
+impl ReadingFilter {
+    pub fn new() -> ReadingFilter {
+        ReadingFilter::default()
+    }
+
+    /// Ignores value changes within `tolerance` of the last reading let through.
+    pub fn deadband(mut self, tolerance: Tolerance) -> Self {
+        self.deadband = Some(tolerance);
+        self
+    }
+
+    /// Lets a reading through at least every `interval`, even if it didn't change.
+    pub fn heartbeat(mut self, interval: Duration) -> Self {
+        self.heartbeat = Some(interval);
+        self
+    }
+
+    /// Tells whether the reading should be kept, remembering it if so.
+    pub fn accept(&mut self, reading: &TimedMeasurement) -> bool {
+        let keep = match &self.last {
+            None => true,
+            Some(last) => {
+                self.heartbeat.is_some_and(|interval| {
+                    reading.received.saturating_duration_since(last.received) >= interval
+                }) || last.measurement.displayed_icons != reading.measurement.displayed_icons
+                    || self.value_changed(last, reading)
+            }
+        };
+        if keep {
+            self.last = Some(reading.clone());
+        }
+        keep
+    }
+
+    fn value_changed(&self, last: &TimedMeasurement, reading: &TimedMeasurement) -> bool {
+        let (last, reading) = (&last.measurement, &reading.measurement);
+        let (Some(tolerance), Some(from), Some(to)) =
+            (self.deadband, last.value(), reading.value())
+        else {
+            return last.displayed_value != reading.displayed_value;
+        };
+
+        let allowed = match tolerance {
+            Tolerance::Digits(digits) => digits as f64 * reading.resolution().unwrap_or_default(),
+            Tolerance::Percent(percent) => percent / 100.0 * from.abs(),
+        };
+        // Leave some room for the rounding of the display
+        (to - from).abs() > allowed + 1e-9
+    }
+
+    /// Applies the filter to a measurement stream. Errors are passed through.
+    pub fn apply<'a>(mut self, stream: MeasurementStream<'a>) -> MeasurementStream<'a> {
+        Box::pin(stream.filter(move |item| {
+            future::ready(match item {
+                Ok(reading) => self.accept(reading),
+                Err(_) => true,
+            })
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::time::Instant;
+
+    use futures::stream;
+
+    use super::*;
+    use crate::parser::DisplayIcon;
+    use crate::timed::reading;
+
+    fn readings(texts: &[(&str, DisplayIcon)]) -> Vec<TimedMeasurement> {
+        let start = Instant::now();
+        texts
+            .iter()
+            .enumerate()
+            .map(|(i, (text, icon))| TimedMeasurement {
+                received: start + Duration::from_millis(500 * i as u64),
+                sequence: i as u64,
+                ..reading(text, &[icon.clone(), DisplayIcon::DC])
+            })
+            .collect()
+    }
+
+    fn kept(mut filter: ReadingFilter, readings: &[TimedMeasurement]) -> Vec<u64> {
+        readings
+            .iter()
+            .filter(|r| filter.accept(r))
+            .map(|r| r.sequence)
+            .collect()
+    }
+
+    #[test]
+    fn test_filters() {
+        let volt = DisplayIcon::Volt;
+        let readings = readings(&[
+            ("1.000", volt.clone()),
+            ("1.000", volt.clone()),
+            ("1.001", volt.clone()),
+            ("1.001", volt.clone()),
+            ("1.000", DisplayIcon::Ampere),
+            ("1.000", DisplayIcon::Ampere),
+            ("1.003", DisplayIcon::Ampere),
+            ("1.003", DisplayIcon::Ampere),
+            ("1.003", DisplayIcon::Ampere),
+            (" .0L ", DisplayIcon::Ampere),
+        ]);
+
+        assert_eq!(kept(ReadingFilter::new(), &readings), [0, 2, 4, 6, 9]);
+        assert_eq!(
+            kept(
+                ReadingFilter::new().deadband(Tolerance::Digits(2)),
+                &readings
+            ),
+            [0, 4, 6, 9]
+        );
+        assert_eq!(
+            kept(
+                ReadingFilter::new().deadband(Tolerance::Percent(1.0)),
+                &readings
+            ),
+            [0, 4, 9]
+        );
+        assert_eq!(
+            kept(
+                ReadingFilter::new().heartbeat(Duration::from_secs(1)),
+                &readings
+            ),
+            [0, 2, 4, 6, 8, 9]
+        );
+
+        let stream: MeasurementStream = Box::pin(stream::iter(
+            readings
+                .into_iter()
+                .map(Ok::<_, Box<dyn Error>>)
+                .chain([Err("lost".into())]),
+        ));
+        let items: Vec<_> = async_std::task::block_on(ReadingFilter::new().apply(stream).collect());
+        assert_eq!(items.len(), 6);
+        assert!(items[5].is_err());
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+use futures::{StreamExt, stream};
+
+use crate::device::MeasurementStream;
+use crate::parser::{DisplayValue, Measurement};
+use crate::settle::Tolerance;
+use crate::timed::TimedMeasurement;
+
+/// Kind of transitional frame, as sent while the meter changes range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Glitch {
+    /// Some digits didn't decode (`?` in the displayed text).
+    UnknownGlyph,
+    /// A single reading far from both the previous and the next one.
+    Spike,
+    /// A single reading in another mode or unit than the previous and the
+    /// next one.
+    UnitFlip,
+}
+
+impl fmt::Display for Glitch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Glitch::UnknownGlyph => "unknown glyph",
+            Glitch::Spike => "spike",
+            Glitch::UnitFlip => "unit flip",
+        })
+    }
+}
+
+/// Reading let out of a `GlitchFilter`, flagged if it looks transitional.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckedReading {
+    pub reading: TimedMeasurement,
+    pub glitch: Option<Glitch>,
+}
+
+/// Flags the transitional frames sent while the meter changes range, so they
+/// don't show up as jumps in plots.
+///
+/// Spikes and unit flips are only known once the next reading shows the
+/// previous value again, so readings are let out one reading late.
+#[derive(Debug, Clone)]
+pub struct GlitchFilter {
+    unknown_glyphs: bool,
+    unit_flips: bool,
+    spikes: Option<Tolerance>,
+    /// Last reading let out that wasn't a glitch.
+    last: Option<TimedMeasurement>,
+    /// Reading waiting for the next one to be checked.
+    pending: Option<TimedMeasurement>,
+    /// Readings with unknown glyphs received after the pending one.
+    held: Vec<TimedMeasurement>,
+}
+
+impl Default for GlitchFilter {
+    fn default() -> GlitchFilter {
+        GlitchFilter {
+            unknown_glyphs: true,
+            unit_flips: true,
+            spikes: Some(Tolerance::Percent(10.0)),
+            last: None,
+            pending: None,
+            held: Vec::new(),
+        }
+    }
+}
+
+impl GlitchFilter {
+    /// Flags unknown glyphs, unit flips and spikes of more than 10%.
+    pub fn new() -> GlitchFilter {
+        GlitchFilter::default()
+    }
+
+    pub fn unknown_glyphs(mut self, check: bool) -> Self {
+        self.unknown_glyphs = check;
+        self
+    }
+
+    pub fn unit_flips(mut self, check: bool) -> Self {
+        self.unit_flips = check;
+        self
+    }
+
+    /// Flags readings further than `tolerance` from both neighbours while
+    /// these agree within it, or doesn't look for spikes with `None`.
+    pub fn spikes(mut self, tolerance: Option<Tolerance>) -> Self {
+        self.spikes = tolerance;
+        self
+    }
+
+    /// Feeds the next reading, returning the readings that can be let out.
+    pub fn push(&mut self, reading: &TimedMeasurement) -> Vec<CheckedReading> {
+        let unknown_glyph = matches!(
+            &reading.measurement.displayed_value,
+            DisplayValue::Text(text) if text.contains('?')
+        );
+        if self.unknown_glyphs && unknown_glyph {
+            if self.pending.is_some() {
+                self.held.push(reading.clone());
+                return Vec::new();
+            }
+            return vec![CheckedReading {
+                reading: reading.clone(),
+                glitch: Some(Glitch::UnknownGlyph),
+            }];
+        }
+
+        let mut checked = Vec::new();
+        if let Some(pending) = self.pending.take() {
+            let glitch = self.check(&pending, reading);
+            checked.push(self.let_out(pending, glitch));
+        }
+        checked.extend(self.drain_held());
+        self.pending = Some(reading.clone());
+        checked
+    }
+
+    /// Lets out the reading waiting for the next one, as it is.
+    pub fn flush(&mut self) -> Vec<CheckedReading> {
+        let mut checked = Vec::new();
+        if let Some(pending) = self.pending.take() {
+            checked.push(self.let_out(pending, None));
+        }
+        checked.extend(self.drain_held());
+        checked
+    }
+
+    /// Applies the filter to a measurement stream, dropping the glitches.
+    /// Errors are passed through, after the reading held back.
+    pub fn apply<'a>(self, stream: MeasurementStream<'a>) -> MeasurementStream<'a> {
+        let state = (stream, self, VecDeque::new(), false);
+        Box::pin(stream::unfold(
+            state,
+            |(mut stream, mut filter, mut queue, mut ended)| async move {
+                loop {
+                    if let Some(item) = queue.pop_front() {
+                        return Some((item, (stream, filter, queue, ended)));
+                    }
+                    if ended {
+                        return None;
+                    }
+                    let (checked, error) = match stream.next().await {
+                        Some(Ok(reading)) => (filter.push(&reading), None),
+                        Some(Err(err)) => (filter.flush(), Some(err)),
+                        None => {
+                            ended = true;
+                            (filter.flush(), None)
+                        }
+                    };
+                    queue.extend(
+                        checked
+                            .into_iter()
+                            .filter(|checked| checked.glitch.is_none())
+                            .map(|checked| Ok(checked.reading)),
+                    );
+                    queue.extend(error.map(Err));
+                }
+            },
+        ))
+    }
+
+    fn check(&self, reading: &TimedMeasurement, next: &TimedMeasurement) -> Option<Glitch> {
+        let last = &self.last.as_ref()?.measurement;
+        let (reading, next) = (&reading.measurement, &next.measurement);
+        let same_unit =
+            |a: &Measurement, b: &Measurement| a.mode() == b.mode() && a.value_unit == b.value_unit;
+
+        if !same_unit(last, next) {
+            return None;
+        }
+        if !same_unit(reading, last) {
+            return self.unit_flips.then_some(Glitch::UnitFlip);
+        }
+
+        let tolerance = self.spikes?;
+        let (Some(before), Some(value), Some(after)) =
+            (last.value(), reading.value(), next.value())
+        else {
+            return None;
+        };
+        let allowed = match tolerance {
+            Tolerance::Digits(digits) => digits as f64 * reading.resolution().unwrap_or_default(),
+            Tolerance::Percent(percent) => percent / 100.0 * before.abs().max(after.abs()),
+        };
+        // Leave some room for the rounding of the display
+        let far = |a: f64, b: f64| (a - b).abs() > allowed + 1e-9;
+        (far(value, before) && far(value, after) && !far(before, after)).then_some(Glitch::Spike)
+    }
+
+    fn let_out(&mut self, reading: TimedMeasurement, glitch: Option<Glitch>) -> CheckedReading {
+        if glitch.is_none() {
+            self.last = Some(reading.clone());
+        }
+        CheckedReading { reading, glitch }
+    }
+
+    fn drain_held(&mut self) -> Vec<CheckedReading> {
+        self.held
+            .drain(..)
+            .map(|reading| CheckedReading {
+                reading,
+                glitch: Some(Glitch::UnknownGlyph),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::DisplayIcon;
+    use crate::timed::reading;
+
+    fn dc(text: &str, icon: DisplayIcon, sequence: u64) -> TimedMeasurement {
+        TimedMeasurement {
+            sequence,
+            ..reading(text, &[icon, DisplayIcon::DC])
+        }
+    }
+
+    fn glitches(
+        filter: &mut GlitchFilter,
+        readings: &[TimedMeasurement],
+    ) -> Vec<(u64, Option<Glitch>)> {
+        let mut checked: Vec<_> = readings.iter().flat_map(|r| filter.push(r)).collect();
+        checked.extend(filter.flush());
+        checked
+            .into_iter()
+            .map(|c| (c.reading.sequence, c.glitch))
+            .collect()
+    }
+
+    #[test]
+    fn test_glitches() {
+        let mut unknown = dc("1.234", DisplayIcon::Volt, 2);
+        unknown.measurement.displayed_value = DisplayValue::Text("1.?34".to_string());
+        let readings = [
+            dc("1.230", DisplayIcon::Volt, 0),
+            dc("1.231", DisplayIcon::Volt, 1),
+            unknown,
+            dc("9.999", DisplayIcon::Volt, 3),
+            dc("1.232", DisplayIcon::Volt, 4),
+            dc("123.2", DisplayIcon::MilliVolt, 5),
+            dc("1.233", DisplayIcon::Volt, 6),
+            // A real range change
+            dc("12.34", DisplayIcon::MilliVolt, 7),
+            dc("12.35", DisplayIcon::MilliVolt, 8),
+        ];
+
+        let mut filter = GlitchFilter::new();
+        assert_eq!(
+            glitches(&mut filter, &readings),
+            [
+                (0, None),
+                (1, None),
+                (2, Some(Glitch::UnknownGlyph)),
+                (3, Some(Glitch::Spike)),
+                (4, None),
+                (5, Some(Glitch::UnitFlip)),
+                (6, None),
+                (7, None),
+                (8, None),
+            ]
+        );
+
+        let mut filter = GlitchFilter::new()
+            .unknown_glyphs(false)
+            .unit_flips(false)
+            .spikes(None);
+        assert!(
+            glitches(&mut filter, &readings)
+                .iter()
+                .all(|(_, g)| g.is_none())
+        );
+    }
+
+    #[test]
+    fn test_glitch_stream() {
+        let readings = vec![
+            Ok(dc("1.230", DisplayIcon::Volt, 0)),
+            Ok(dc("5.000", DisplayIcon::Volt, 1)),
+            Ok(dc("1.230", DisplayIcon::Volt, 2)),
+            Err("lost".into()),
+            Ok(dc("1.230", DisplayIcon::Volt, 3)),
+        ];
+        let stream: MeasurementStream = Box::pin(stream::iter(readings));
+        let items: Vec<_> = async_std::task::block_on(GlitchFilter::new().apply(stream).collect());
+        let sequences: Vec<_> = items
+            .iter()
+            .map(|item| item.as_ref().map(|r| r.sequence).ok())
+            .collect();
+        assert_eq!(sequences, [Some(0), Some(2), None, Some(3)]);
+    }
+}
@@ -0,0 +1,216 @@
+//! Capture of data points with the HOLD button of the meter, leaving both
+//! hands on the probes.
+//!
+//! The labels of the points can be read from a text file with one label per
+//! line, in order; blank lines and lines starting with `#` are ignored:
+//!
+//! ```text
+//! # Power supply board
+//! TP1 3V3
+//! TP2 5V
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::parser::DisplayValue;
+use crate::state::{MeterEventKind, MeterState};
+use crate::timed::TimedMeasurement;
+
+/// Reading held by the meter, recorded as a data point.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapturedPoint {
+    pub label: String,
+    pub reading: TimedMeasurement,
+}
+
+impl fmt::Display for CapturedPoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let measurement = &self.reading.measurement;
+        write!(f, "{}: ", self.label)?;
+        match &measurement.displayed_value {
+            DisplayValue::Number(_) => write!(f, "{}", measurement.value().unwrap_or_default())?,
+            DisplayValue::Text(text) => write!(f, "{}", text.trim())?,
+        }
+        if let Some(unit) = &measurement.value_unit {
+            write!(f, " {}", unit)?;
+        }
+        Ok(())
+    }
+}
+
+/// Records a data point each time the HOLD button of the meter is pressed,
+/// taking the held value.
+///
+/// Readings are ignored while the hold stays on, as is a hold already on
+/// when the capture starts. Points are labelled from a list of test points,
+/// or numbered when there is none.
+#[derive(Debug, Clone, Default)]
+pub struct HoldCapture {
+    state: MeterState,
+    started: bool,
+    labels: Vec<String>,
+    points: Vec<CapturedPoint>,
+}
+
+impl HoldCapture {
+    /// Numbers the points from 1.
+    pub fn new() -> HoldCapture {
+        HoldCapture::default()
+    }
+
+    /// Labels the points from `labels`, in order, until all are captured.
+    pub fn with_labels(labels: Vec<String>) -> HoldCapture {
+        HoldCapture {
+            labels,
+            ..HoldCapture::default()
+        }
+    }
+
+    /// Parses the labels file format described in the module documentation.
+    pub fn parse(text: &str) -> HoldCapture {
+        HoldCapture::with_labels(
+            text.lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(str::to_string)
+                .collect(),
+        )
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<HoldCapture, Box<dyn Error>> {
+        Ok(HoldCapture::parse(&fs::read_to_string(path)?))
+    }
+
+    /// Label of the next point, `None` once all the listed points are captured.
+    pub fn next_label(&self) -> Option<String> {
+        let index = self.points.len();
+        if self.labels.is_empty() {
+            Some((index + 1).to_string())
+        } else {
+            self.labels.get(index).cloned()
+        }
+    }
+
+    /// Feeds the next reading, returning the point recorded if the hold was
+    /// just engaged.
+    pub fn push(&mut self, reading: &TimedMeasurement) -> Option<&CapturedPoint> {
+        let events = self.state.push(reading);
+        let started = std::mem::replace(&mut self.started, true);
+        if !started
+            || !events
+                .iter()
+                .any(|event| event.kind == MeterEventKind::HoldEngaged)
+        {
+            return None;
+        }
+
+        let label = self.next_label()?;
+        self.points.push(CapturedPoint {
+            label,
+            reading: reading.clone(),
+        });
+        self.points.last()
+    }
+
+    /// Tells whether all the listed points were captured.
+    pub fn is_done(&self) -> bool {
+        self.next_label().is_none()
+    }
+
+    pub fn points(&self) -> &[CapturedPoint] {
+        &self.points
+    }
+
+    /// Writes the captured points as CSV.
+    pub fn write_csv(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(writer, "label,time,value,unit")?;
+        for point in &self.points {
+            let measurement = &point.reading.measurement;
+            writeln!(
+                writer,
+                "\"{}\",{},{},{}",
+                point.label.replace('"', "\"\""),
+                point.reading.timestamp.to_rfc3339(),
+                measurement.value().map_or(String::new(), |v| v.to_string()),
+                measurement.value_unit.as_deref().unwrap_or("")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::DisplayIcon;
+    use crate::timed::reading;
+
+    fn volts(text: &str, hold: bool) -> TimedMeasurement {
+        let mut icons = vec![DisplayIcon::Volt, DisplayIcon::DC];
+        if hold {
+            icons.push(DisplayIcon::Hold);
+        }
+        reading(text, &icons)
+    }
+
+    fn labels(capture: &mut HoldCapture, readings: &[(&str, bool)]) -> Vec<String> {
+        readings
+            .iter()
+            .filter_map(|(text, hold)| capture.push(&volts(text, *hold)).map(|p| p.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_numbered_points() {
+        let mut capture = HoldCapture::new();
+        let points = labels(
+            &mut capture,
+            &[
+                // Held before the capture started
+                ("9.000", true),
+                ("1.000", false),
+                ("1.234", true),
+                ("1.234", true),
+                ("3.300", false),
+                ("3.301", true),
+                (" .0L ", false),
+                (" .0L ", true),
+            ],
+        );
+        assert_eq!(points, ["1: 1.234 V", "2: 3.301 V", "3: .0L V"]);
+        assert!(!capture.is_done());
+        assert_eq!(capture.next_label().as_deref(), Some("4"));
+
+        let mut csv = Vec::new();
+        capture.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[1].starts_with("\"1\","));
+        assert!(lines[1].ends_with(",1.234,V"));
+        assert!(lines[3].ends_with(",,V"));
+    }
+
+    #[test]
+    fn test_listed_points() {
+        let mut capture = HoldCapture::parse("# Board\nTP1 3V3\n\nTP2 5V\n");
+        let points = labels(
+            &mut capture,
+            &[
+                ("3.300", false),
+                ("3.300", true),
+                ("5.000", false),
+                ("5.000", true),
+                ("5.000", false),
+                ("5.000", true),
+            ],
+        );
+        assert_eq!(points, ["TP1 3V3: 3.3 V", "TP2 5V: 5 V"]);
+        assert!(capture.is_done());
+        assert_eq!(capture.points().len(), 2);
+    }
+}
@@ -1,7 +1,88 @@
+pub use accuracy::Accuracy;
+pub use accuracy::AccuracySpec;
+pub use accuracy::Uncertainty;
+pub use adapter::AdapterSelector;
+pub use adapter::BluetoothAdapter;
+pub use adapter::list_adapters;
+pub use adapter::select_adapter;
+pub use aggregate::WindowAggregator;
+pub use aggregate::WindowSummary;
+pub use alarm::Action;
+pub use alarm::AlarmAction;
+pub use alarm::AlarmEngine;
+pub use alarm::AlarmEvent;
+pub use alarm::AlarmRule;
+pub use alarm::AlarmState;
+pub use alarm::Condition;
+pub use battery::BatteryReport;
+pub use battery::BatteryTest;
+pub use battery::Cutoff;
+pub use battery::StopReason;
+pub use calibration::CalibrationProfile;
+pub use calibration::Calibrations;
+pub use calibration::Correction;
+pub use calibration::MeterRange;
 pub use device::DmmDevice;
+pub use device::MeasurementStream;
 pub use device::scan_for_dmm;
+pub use device::scan_for_dmms;
+pub use eseries::BinResult;
+pub use eseries::BinTally;
+pub use eseries::ComponentSorter;
+pub use eseries::ESeries;
+pub use filter::ReadingFilter;
+pub use glitch::CheckedReading;
+pub use glitch::Glitch;
+pub use glitch::GlitchFilter;
+pub use hold::CapturedPoint;
+pub use hold::HoldCapture;
+pub use link::LinkStats;
+pub use link::RssiStats;
+#[cfg(any(test, feature = "mock"))]
+pub use mock::MockTransport;
+pub use parser::DisplayIcon;
 pub use parser::DisplayValue;
 pub use parser::Measurement;
+pub use parser::Mode;
+pub use power::PowerMeter;
+pub use power::PowerSample;
+pub use power::PowerStream;
+pub use profile::Button;
+pub use profile::Command;
+pub use profile::ModelProfile;
+pub use profile::profile_for;
+pub use relative::RelativeMode;
+pub use relative::RelativeReading;
+pub use replay::Capture;
+pub use replay::CapturedFrame;
+pub use replay::ReplayTransport;
+pub use settle::SettleDetector;
+pub use settle::StableReading;
+pub use settle::Tolerance;
+pub use simulator::Scenario;
+pub use simulator::SimulatorTransport;
+pub use state::MeterEvent;
+pub use state::MeterEventKind;
+pub use state::MeterState;
+pub use stats::Statistics;
+pub use stats::StatsTracker;
+pub use timed::Frame;
+pub use timed::TimedMeasurement;
+pub use transport::NotificationStream;
+pub use transport::Transport;
+pub use trend::Trend;
+pub use trend::TrendTracker;
+pub use trigger::Trigger;
+pub use trigger::TriggerBuffer;
+pub use trigger::TriggerUpdate;
+pub use trigger::TriggeredCapture;
+pub use units::Quantity;
+pub use units::TemperatureUnit;
+pub use units::UnitPreferences;
+pub use verify::PointResult;
+pub use verify::ReferencePoint;
+pub use verify::Verification;
+pub use verify::VerificationReport;
 
 #[derive(Debug, thiserror::Error)]
 pub enum DmmError {
@@ -17,9 +98,90 @@ pub enum DmmError {
     #[error("Device not found")]
     DeviceNotFound,
 
+    #[error("Measurement characteristic not found")]
+    CharacteristicNotFound,
+
+    #[error("Bluetooth adapter not found: {0}")]
+    AdapterNotFound(String),
+
+    #[error("Operation not supported by this meter")]
+    Unsupported,
+
+    #[error("Invalid frame: {0}")]
+    InvalidFrame(String),
+
+    #[error("Cannot encode frame: {0}")]
+    Encoding(String),
+
+    #[error("Invalid capture: {0}")]
+    InvalidCapture(String),
+
+    #[error("Invalid scenario: {0}")]
+    InvalidScenario(String),
+
+    #[error("Invalid playback setting: {0}")]
+    InvalidPlayback(String),
+
+    #[error("Unknown mode: {0}")]
+    UnknownMode(String),
+
+    #[error("Invalid alarm rules: {0}")]
+    InvalidAlarm(String),
+
+    #[error("Invalid calibration: {0}")]
+    InvalidCalibration(String),
+
+    #[error("Invalid accuracy: {0}")]
+    InvalidAccuracy(String),
+
+    #[error("Invalid reference points: {0}")]
+    InvalidReference(String),
+
+    #[error("Invalid trigger: {0}")]
+    InvalidTrigger(String),
+
+    #[error("Invalid tolerance: {0}")]
+    InvalidTolerance(String),
+
+    #[error("Invalid window: {0}")]
+    InvalidWindow(String),
+
+    #[error("Unknown E-series: {0}")]
+    UnknownSeries(String),
+
+    #[error("Unknown unit: {0}")]
+    UnknownUnit(String),
+
     #[error("Unknown error: {0}")]
     Unknown(String),
 }
 
+mod accuracy;
+mod adapter;
+mod aggregate;
+mod alarm;
+mod battery;
+mod calibration;
 mod device;
+mod eseries;
+mod filter;
+mod glitch;
+mod hold;
+mod link;
+#[cfg(any(test, feature = "mock"))]
+mod mock;
 mod parser;
+mod power;
+mod profile;
+mod relative;
+mod replay;
+mod settle;
+mod simulator;
+mod state;
+mod stats;
+mod timed;
+mod transport;
+mod trend;
+mod trigger;
+mod units;
+mod verify;
@@ -0,0 +1,161 @@
+use std::time::{Duration, Instant};
+
+/// Gaps longer than this many average intervals are counted as lost frames.
+const GAP_FACTOR: f64 = 1.5;
+/// Intervals needed before the average is trusted to detect gaps.
+const MIN_INTERVALS: u64 = 5;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RssiStats {
+    pub samples: u64,
+    pub last: i16,
+    pub min: i16,
+    pub max: i16,
+    pub mean: f64,
+}
+
+/// Snapshot of the quality of the link with a meter since it was connected.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LinkStats {
+    /// Notifications received on the measurement characteristic.
+    pub frames: u64,
+    /// Frames that could not be decoded.
+    pub invalid_frames: u64,
+    /// Frames estimated to be lost, from gaps in the notification timing.
+    pub dropped_frames: u64,
+    pub timeouts: u64,
+    /// Average notification rate in Hz, excluding gaps.
+    pub frame_rate: Option<f64>,
+    pub mean_interval: Option<Duration>,
+    /// Standard deviation of the time between notifications, excluding gaps.
+    pub jitter: Option<Duration>,
+    pub rssi: Option<RssiStats>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct LinkTracker {
+    stats: LinkStats,
+    last_frame: Option<Instant>,
+    // Welford's running mean and variance of the intervals, in seconds
+    intervals: u64,
+    mean: f64,
+    m2: f64,
+}
+
+impl LinkTracker {
+    pub fn frame_received(&mut self, at: Instant) {
+        self.stats.frames += 1;
+
+        if let Some(last) = self.last_frame.replace(at) {
+            let interval = at.saturating_duration_since(last).as_secs_f64();
+            // Frames delivered in a burst can share an instant, leaving no
+            // average to compare against
+            if self.intervals >= MIN_INTERVALS
+                && self.mean > 0.0
+                && interval > self.mean * GAP_FACTOR
+            {
+                let lost = (interval / self.mean).round() as u64 - 1;
+                self.stats.dropped_frames = self.stats.dropped_frames.saturating_add(lost);
+                return;
+            }
+
+            self.intervals += 1;
+            let delta = interval - self.mean;
+            self.mean += delta / self.intervals as f64;
+            self.m2 += delta * (interval - self.mean);
+        }
+    }
+
+    pub fn invalid_frame(&mut self) {
+        self.stats.invalid_frames += 1;
+    }
+
+    pub fn timeout(&mut self) {
+        self.stats.timeouts += 1;
+        // Don't count the silence as an interval
+        self.last_frame = None;
+    }
+
+    pub fn rssi(&mut self, rssi: i16) {
+        let stats = self.stats.rssi.get_or_insert(RssiStats {
+            samples: 0,
+            last: rssi,
+            min: rssi,
+            max: rssi,
+            mean: 0.0,
+        });
+        stats.samples += 1;
+        stats.last = rssi;
+        stats.min = stats.min.min(rssi);
+        stats.max = stats.max.max(rssi);
+        stats.mean += (rssi as f64 - stats.mean) / stats.samples as f64;
+    }
+
+    pub fn snapshot(&self) -> LinkStats {
+        let mut stats = self.stats.clone();
+        if self.intervals > 0 && self.mean > 0.0 {
+            stats.frame_rate = Some(1.0 / self.mean);
+            stats.mean_interval = Some(Duration::from_secs_f64(self.mean));
+            stats.jitter = Some(Duration::from_secs_f64(
+                (self.m2 / self.intervals as f64).sqrt(),
+            ));
+        }
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_link_tracker() {
+        let mut tracker = LinkTracker::default();
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+
+        for t in [0, 500, 1000, 1500, 2000, 2500, 4000, 4600, 5000] {
+            tracker.frame_received(start + ms(t));
+        }
+        tracker.invalid_frame();
+        tracker.timeout();
+        tracker.frame_received(start + ms(60000));
+        tracker.rssi(-70);
+        tracker.rssi(-60);
+
+        let stats = tracker.snapshot();
+        assert_eq!(stats.frames, 10);
+        assert_eq!(stats.invalid_frames, 1);
+        assert_eq!(stats.timeouts, 1);
+        // The 1.5 s gap hides two frames
+        assert_eq!(stats.dropped_frames, 2);
+        let mean = stats.mean_interval.unwrap().as_secs_f64();
+        assert!((mean - 0.5).abs() < 1e-6);
+        assert!((stats.frame_rate.unwrap() - 2.0).abs() < 1e-6);
+        // Intervals of 500 ms but one of 600 ms and one of 400 ms
+        let jitter = stats.jitter.unwrap().as_secs_f64();
+        assert!((jitter - (0.02f64 / 7.0).sqrt()).abs() < 1e-6);
+
+        let rssi = stats.rssi.unwrap();
+        assert_eq!(rssi.samples, 2);
+        assert_eq!(rssi.last, -60);
+        assert_eq!(rssi.min, -70);
+        assert_eq!(rssi.max, -60);
+        assert_eq!(rssi.mean, -65.0);
+    }
+
+    #[test]
+    fn test_link_tracker_identical_instants() {
+        let mut tracker = LinkTracker::default();
+        let start = Instant::now();
+
+        for _ in 0..10 {
+            tracker.frame_received(start);
+        }
+        tracker.frame_received(start + Duration::from_secs(1));
+
+        let stats = tracker.snapshot();
+        assert_eq!(stats.frames, 11);
+        assert_eq!(stats.dropped_frames, 0);
+    }
+}
@@ -0,0 +1,100 @@
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+
+use async_std::channel::{self, Receiver, Sender};
+use async_trait::async_trait;
+use btleplug::api::ValueNotification;
+use futures::StreamExt;
+use uuid::Uuid;
+
+use crate::DmmError;
+use crate::device::DMM_CHARACTERISTIC_UUID;
+use crate::transport::{NotificationStream, Transport};
+
+#[derive(Debug, Default)]
+struct MockState {
+    connected: bool,
+    written: Vec<(Uuid, Vec<u8>)>,
+}
+
+/// In-memory transport for tests: frames are fed by hand and writes are recorded.
+#[derive(Debug, Clone)]
+pub struct MockTransport {
+    sender: Sender<ValueNotification>,
+    receiver: Receiver<ValueNotification>,
+    state: Arc<Mutex<MockState>>,
+}
+
+impl Default for MockTransport {
+    fn default() -> Self {
+        MockTransport::new()
+    }
+}
+
+impl MockTransport {
+    pub fn new() -> MockTransport {
+        let (sender, receiver) = channel::unbounded();
+        MockTransport {
+            sender,
+            receiver,
+            state: Arc::new(Mutex::new(MockState::default())),
+        }
+    }
+
+    /// Queues a frame as if it was notified by the meter.
+    pub fn push_frame(&self, data: &[u8]) {
+        self.push_notification(DMM_CHARACTERISTIC_UUID, data);
+    }
+
+    pub fn push_notification(&self, uuid: Uuid, data: &[u8]) {
+        // The channel is only closed by `close`, after which frames are dropped
+        let _ = self.sender.try_send(ValueNotification {
+            uuid,
+            value: data.to_vec(),
+        });
+    }
+
+    /// Ends the notification stream once the queued frames are consumed, like
+    /// a meter going out of range.
+    pub fn close(&self) {
+        self.sender.close();
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.state.lock().unwrap().connected
+    }
+
+    /// Returns everything written to the meter so far.
+    pub fn written(&self) -> Vec<(Uuid, Vec<u8>)> {
+        self.state.lock().unwrap().written.clone()
+    }
+}
+
+#[async_trait]
+impl Transport for MockTransport {
+    async fn connect(&self) -> Result<(), Box<dyn Error>> {
+        self.state.lock().unwrap().connected = true;
+        Ok(())
+    }
+
+    async fn disconnect(&self) -> Result<(), Box<dyn Error>> {
+        self.state.lock().unwrap().connected = false;
+        Ok(())
+    }
+
+    async fn notifications(&self) -> Result<NotificationStream, Box<dyn Error>> {
+        if !self.is_connected() {
+            return Err(DmmError::NotConnected.into());
+        }
+        Ok(self.receiver.clone().boxed())
+    }
+
+    async fn write(&self, characteristic: Uuid, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        let mut state = self.state.lock().unwrap();
+        if !state.connected {
+            return Err(DmmError::NotConnected.into());
+        }
+        state.written.push((characteristic, data.to_vec()));
+        Ok(())
+    }
+}
@@ -1,8 +1,15 @@
 use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
 
 use packed_struct::prelude::*;
 use phf::phf_map;
 
+use crate::DmmError;
+use crate::accuracy::{Accuracy, Uncertainty};
+use crate::profile::ModelProfile;
+use crate::units::{Quantity, TemperatureUnit};
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum DisplayValue {
     Number(f32),
@@ -94,6 +101,17 @@ impl SevenSegmentDisplay {
     pub fn get_text(&self) -> Option<char> {
         SEGMENTS_MAP.get(&(self.segments & 0b111_1111)).cloned()
     }
+
+    pub fn from_char(c: char, dot_dash: bool) -> Option<SevenSegmentDisplay> {
+        let (&segments, _) = SEGMENTS_MAP.entries().find(|(_, &v)| v == c)?;
+        Some(SevenSegmentDisplay {
+            segments: if dot_dash {
+                segments | 0b10000000
+            } else {
+                segments
+            },
+        })
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -200,6 +218,86 @@ static DMM_1_ICONS: [DisplayIcon; 32] = [
     DisplayIcon::Unknown(" "),
 ];
 
+/// What the meter is measuring, as told by its icons.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Mode {
+    DcVoltage,
+    AcVoltage,
+    DcCurrent,
+    AcCurrent,
+    Resistance,
+    Continuity,
+    Diode,
+    Capacitance,
+    Frequency,
+    DutyCycle,
+    Temperature,
+}
+
+impl Mode {
+    pub const ALL: [Mode; 11] = [
+        Mode::DcVoltage,
+        Mode::AcVoltage,
+        Mode::DcCurrent,
+        Mode::AcCurrent,
+        Mode::Resistance,
+        Mode::Continuity,
+        Mode::Diode,
+        Mode::Capacitance,
+        Mode::Frequency,
+        Mode::DutyCycle,
+        Mode::Temperature,
+    ];
+
+    /// Short name, as used in the scenario and configuration files.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mode::DcVoltage => "dc-volts",
+            Mode::AcVoltage => "ac-volts",
+            Mode::DcCurrent => "dc-amps",
+            Mode::AcCurrent => "ac-amps",
+            Mode::Resistance => "ohms",
+            Mode::Continuity => "continuity",
+            Mode::Diode => "diode",
+            Mode::Capacitance => "capacitance",
+            Mode::Frequency => "frequency",
+            Mode::DutyCycle => "duty-cycle",
+            Mode::Temperature => "temperature",
+        }
+    }
+
+    /// Unit of the base values of the mode (see `Measurement::base_value`).
+    /// Temperatures are in °C, whatever the unit displayed by the meter.
+    pub fn unit(&self) -> &'static str {
+        match self {
+            Mode::DcVoltage | Mode::AcVoltage | Mode::Diode => "V",
+            Mode::DcCurrent | Mode::AcCurrent => "A",
+            Mode::Resistance | Mode::Continuity => "Ω",
+            Mode::Capacitance => "F",
+            Mode::Frequency => "Hz",
+            Mode::DutyCycle => "%",
+            Mode::Temperature => "°C",
+        }
+    }
+}
+
+impl FromStr for Mode {
+    type Err = DmmError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Mode::ALL
+            .into_iter()
+            .find(|mode| mode.name() == s)
+            .ok_or_else(|| DmmError::UnknownMode(s.to_string()))
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Measurement {
     pub dmm_id: u8,
@@ -209,6 +307,8 @@ pub struct Measurement {
     pub value_unit: Option<String>,
 }
 
+const FRAME_PREAMBLE: u16 = 0x5aa5;
+
 static XOR_KEY: [u8; 20] = [
     0x41, 0x21, 0x73, 0x55, 0xa2, 0xc1, 0x32, 0x71, 0x66, 0xaa, 0x3b, 0xd0, 0xe2, 0xa8, 0x33, 0x14,
     0x20, 0x21, 0xaa, 0xbb,
@@ -232,7 +332,11 @@ struct MeasurementData {
 // from_bytes function
 impl Measurement {
     pub fn from_bytes(data: &[u8]) -> Result<Measurement, Box<dyn Error>> {
-        assert_eq!(data.len(), 11);
+        if data.len() != 11 {
+            return Err(
+                DmmError::InvalidFrame(format!("expected 11 bytes, got {}", data.len())).into(),
+            );
+        }
         let decoded: Vec<u8> = data
             .iter()
             .zip(XOR_KEY.iter())
@@ -269,11 +373,7 @@ impl Measurement {
         let displayed_value = DisplayValue::parse(&display_text);
 
         // Extract the displayed icons
-        let icons_map = if data.dmm_id.to_be() == 3 {
-            &DMM_3_ICONS
-        } else {
-            &DMM_1_ICONS
-        };
+        let icons_map = icons_map(data.dmm_id.to_be());
 
         let mut displayed_icons = Vec::new();
         for (i, &icon) in data.icons1.iter().chain(data.icons2.iter()).enumerate() {
@@ -301,6 +401,8 @@ impl Measurement {
             Some("MΩ".to_string())
         } else if displayed_icons.contains(&DisplayIcon::Ohm) {
             Some("Ω".to_string())
+        } else if displayed_icons.contains(&DisplayIcon::NanoFarad) {
+            Some("nF".to_string())
         } else if displayed_icons.contains(&DisplayIcon::MicroFarad) {
             Some("μF".to_string())
         } else if displayed_icons.contains(&DisplayIcon::MilliFarad) {
@@ -332,11 +434,237 @@ impl Measurement {
             value_unit,
         })
     }
+
+    /// Builds the scrambled frame a meter would send to show `text`, as it is
+    /// rendered by `from_bytes`, with the given icons lit.
+    pub fn encode(dmm_id: u8, text: &str, icons: &[DisplayIcon]) -> Result<[u8; 11], DmmError> {
+        let invalid = || DmmError::Encoding(format!("cannot display {:?}", text));
+
+        // A leading minus is the dot of the first digit, unless it is a digit itself
+        let glyphs = text.chars().filter(|&c| c != '.').count();
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(rest) if glyphs == 5 => (true, rest),
+            _ => (false, text),
+        };
+
+        let mut segments = Vec::with_capacity(4);
+        let mut dot = false;
+        for c in digits.chars() {
+            if c == '.' {
+                if segments.is_empty() || dot {
+                    return Err(invalid());
+                }
+                dot = true;
+                continue;
+            }
+            segments.push(SevenSegmentDisplay::from_char(c, dot).ok_or_else(invalid)?);
+            dot = false;
+        }
+        if dot || segments.len() != 4 {
+            return Err(invalid());
+        }
+        if negative {
+            segments[0].segments |= 0b10000000;
+        }
+
+        let segments: [SevenSegmentDisplay; 4] = segments.try_into().unwrap();
+        pack_frame(dmm_id, &segments, icons)
+    }
+
+    /// Turns the measurement back into the frame it was decoded from.
+    pub fn to_bytes(&self) -> Result<[u8; 11], DmmError> {
+        pack_frame(self.dmm_id, &self.display_segments, &self.displayed_icons)
+    }
+
+    pub fn has_icon(&self, icon: &DisplayIcon) -> bool {
+        self.displayed_icons.contains(icon)
+    }
+
+    /// Returns the displayed number, if any.
+    pub fn value(&self) -> Option<f64> {
+        match self.displayed_value {
+            // Go through the shortest representation so 9.57 doesn't become 9.569999694824219
+            DisplayValue::Number(value) => value.to_string().parse().ok(),
+            DisplayValue::Text(_) => None,
+        }
+    }
+
+    /// Returns the displayed number with its unit.
+    pub fn quantity(&self) -> Option<Quantity> {
+        Some(Quantity::new(self.value()?, self.value_unit.as_deref()?))
+    }
+
+    /// Tells whether the meter shows "0L", the input being out of range.
+    pub fn is_overload(&self) -> bool {
+        match &self.displayed_value {
+            DisplayValue::Text(text) => {
+                let text: String = text.chars().filter(|c| !" .-".contains(*c)).collect();
+                text.eq_ignore_ascii_case("0L") || text.eq_ignore_ascii_case("oL")
+            }
+            DisplayValue::Number(_) => false,
+        }
+    }
+
+    /// Returns the displayed number in the base unit of the mode (V, A, Ω, F,
+    /// Hz, °C...), undoing the prefix of the unit.
+    pub fn base_value(&self) -> Option<f64> {
+        self.value().map(|value| self.to_base(value))
+    }
+
+    /// Converts a value in the displayed unit to the base unit of the mode.
+    pub(crate) fn to_base(&self, value: f64) -> f64 {
+        match self.temperature_unit() {
+            Some(unit) => unit.convert(value, TemperatureUnit::Celsius),
+            None => value * self.unit_scale(),
+        }
+    }
+
+    /// Converts a value in the base unit of the mode to the displayed unit.
+    pub(crate) fn to_displayed(&self, value: f64) -> f64 {
+        match self.temperature_unit() {
+            Some(unit) => TemperatureUnit::Celsius.convert(value, unit),
+            None => value / self.unit_scale(),
+        }
+    }
+
+    /// Unit of the displayed temperature; farads are written `F` too.
+    fn temperature_unit(&self) -> Option<TemperatureUnit> {
+        if self.mode()? != Mode::Temperature {
+            return None;
+        }
+        self.value_unit.as_deref()?.parse().ok()
+    }
+
+    /// Returns the factor of the prefix of the unit, e.g. 1e-3 for mV.
+    pub(crate) fn unit_scale(&self) -> f64 {
+        match self.value_unit.as_deref().and_then(|u| u.chars().next()) {
+            Some('n') => 1e-9,
+            Some('μ') | Some('µ') => 1e-6,
+            Some('m') => 1e-3,
+            Some('k') => 1e3,
+            Some('M') => 1e6,
+            _ => 1.0,
+        }
+    }
+
+    /// Returns the weight of the last displayed digit, if a number is displayed.
+    pub fn resolution(&self) -> Option<f64> {
+        self.value()?;
+        Some(10f64.powi(-(self.decimals() as i32)))
+    }
+
+    /// Returns the number of digits after the decimal point of the display.
+    pub(crate) fn decimals(&self) -> u32 {
+        self.display_segments
+            .iter()
+            .skip(1)
+            .position(|s| s.get_dot_dash())
+            .map_or(0, |i| 3 - i as u32)
+    }
+
+    /// Returns the accuracy of the meter in the current mode and range,
+    /// according to `profile`: the one of the device (see `DmmDevice::profile`),
+    /// or `profile_for(dmm_id)` if the device isn't known.
+    pub fn accuracy(&self, profile: &ModelProfile) -> Option<Accuracy> {
+        profile.accuracy_for(self.mode()?, self.value_unit.as_deref())
+    }
+
+    /// Returns the interval the measured quantity lies in, in the displayed
+    /// unit, if a number is displayed and `profile` knows the accuracy of the
+    /// meter.
+    pub fn uncertainty(&self, profile: &ModelProfile) -> Option<Uncertainty> {
+        Some(Uncertainty::new(
+            self.value()?,
+            self.resolution()?,
+            self.accuracy(profile)?,
+        ))
+    }
+
+    pub fn mode(&self) -> Option<Mode> {
+        let any = |icons: &[DisplayIcon]| icons.iter().any(|i| self.has_icon(i));
+        let ac = self.has_icon(&DisplayIcon::AC);
+
+        // The diode mode also lights the buzzer icon
+        if self.has_icon(&DisplayIcon::Diode) {
+            Some(Mode::Diode)
+        } else if self.has_icon(&DisplayIcon::Buzz) {
+            Some(Mode::Continuity)
+        } else if any(&[DisplayIcon::Volt, DisplayIcon::MilliVolt]) {
+            Some(if ac { Mode::AcVoltage } else { Mode::DcVoltage })
+        } else if any(&[
+            DisplayIcon::Ampere,
+            DisplayIcon::MilliAmpere,
+            DisplayIcon::MicroAmpere,
+        ]) {
+            Some(if ac { Mode::AcCurrent } else { Mode::DcCurrent })
+        } else if any(&[DisplayIcon::Ohm, DisplayIcon::KiloOhm, DisplayIcon::MegaOhm]) {
+            Some(Mode::Resistance)
+        } else if any(&[
+            DisplayIcon::Farad,
+            DisplayIcon::NanoFarad,
+            DisplayIcon::MicroFarad,
+            DisplayIcon::MilliFarad,
+        ]) {
+            Some(Mode::Capacitance)
+        } else if self.has_icon(&DisplayIcon::Hertz) {
+            Some(Mode::Frequency)
+        } else if self.has_icon(&DisplayIcon::Percent) {
+            Some(Mode::DutyCycle)
+        } else if any(&[DisplayIcon::DegC, DisplayIcon::DegF]) {
+            Some(Mode::Temperature)
+        } else {
+            None
+        }
+    }
+}
+
+fn icons_map(dmm_id: u8) -> &'static [DisplayIcon; 32] {
+    if dmm_id == 3 {
+        &DMM_3_ICONS
+    } else {
+        &DMM_1_ICONS
+    }
+}
+
+fn pack_frame(
+    dmm_id: u8,
+    segments: &[SevenSegmentDisplay; 4],
+    icons: &[DisplayIcon],
+) -> Result<[u8; 11], DmmError> {
+    let icons_map = icons_map(dmm_id);
+    let mut flags = [false; 31];
+    for icon in icons {
+        let i = icons_map
+            .iter()
+            .position(|i| i == icon)
+            .filter(|&i| i < flags.len())
+            .ok_or_else(|| {
+                DmmError::Encoding(format!("icon {:?} not available on DMM {}", icon, dmm_id))
+            })?;
+        flags[i] = true;
+    }
+
+    let data = MeasurementData {
+        preamble: FRAME_PREAMBLE,
+        dmm_id: dmm_id.into(),
+        icons1: flags[..4].try_into().unwrap(),
+        seven_segments: segments.clone().map(|s| s.segments),
+        icons2: flags[4..].try_into().unwrap(),
+    };
+    let packed = data.pack().map_err(|e| DmmError::Encoding(e.to_string()))?;
+
+    let mut frame = [0u8; 11];
+    for (i, byte) in packed.iter().enumerate() {
+        frame[i] = byte.reverse_bits() ^ XOR_KEY[i];
+    }
+    Ok(frame)
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
+    use crate::profile::DMM_3_PROFILE;
 
     #[test]
     fn test_seven_segment() {
@@ -522,4 +850,145 @@ mod tests {
         );
         assert_eq!(measurement.value_unit, Some("mV".to_string()));
     }
+
+    #[test]
+    fn test_measurement_wrong_length() {
+        let data: [u8; 11] = [27, 132, 112, 161, 73, 154, 188, 126, 102, 218, 58];
+        assert!(Measurement::from_bytes(&data[..10]).is_err());
+    }
+
+    #[test]
+    fn test_mode() {
+        let mode = |text: &str, icons: &[DisplayIcon]| {
+            let frame = Measurement::encode(3, text, icons).unwrap();
+            Measurement::from_bytes(&frame).unwrap().mode()
+        };
+
+        assert_eq!(mode("Auto", &[DisplayIcon::Bluetooth]), None);
+        assert_eq!(
+            mode("-09.57", &[DisplayIcon::Volt, DisplayIcon::DC]),
+            Some(Mode::DcVoltage)
+        );
+        assert_eq!(
+            mode("230.1", &[DisplayIcon::Volt, DisplayIcon::AC]),
+            Some(Mode::AcVoltage)
+        );
+        assert_eq!(
+            mode("12.34", &[DisplayIcon::MilliAmpere, DisplayIcon::DC]),
+            Some(Mode::DcCurrent)
+        );
+        assert_eq!(
+            mode(
+                " .0L ",
+                &[DisplayIcon::Buzz, DisplayIcon::Diode, DisplayIcon::Volt]
+            ),
+            Some(Mode::Diode)
+        );
+        assert_eq!(
+            mode("000.3", &[DisplayIcon::Buzz, DisplayIcon::Ohm]),
+            Some(Mode::Continuity)
+        );
+        assert_eq!(
+            mode("4.700", &[DisplayIcon::KiloOhm]),
+            Some(Mode::Resistance)
+        );
+        assert_eq!(mode("0025", &[DisplayIcon::DegF]), Some(Mode::Temperature));
+
+        for m in Mode::ALL {
+            assert_eq!(m.name().parse::<Mode>().unwrap(), m);
+        }
+        assert!("volts".parse::<Mode>().is_err());
+
+        let frame = Measurement::encode(3, "-09.57", &[DisplayIcon::Volt]).unwrap();
+        let measurement = Measurement::from_bytes(&frame).unwrap();
+        assert_eq!(measurement.value(), Some(-9.57));
+        assert_eq!(measurement.resolution(), Some(0.01));
+        assert_eq!(measurement.base_value(), Some(-9.57));
+        let frame = Measurement::encode(3, "12.34", &[DisplayIcon::MilliAmpere]).unwrap();
+        let base = Measurement::from_bytes(&frame)
+            .unwrap()
+            .base_value()
+            .unwrap();
+        assert!((base - 0.01234).abs() < 1e-12);
+        let frame =
+            Measurement::encode(3, "100.0", &[DisplayIcon::Farad, DisplayIcon::NanoFarad]).unwrap();
+        let measurement = Measurement::from_bytes(&frame).unwrap();
+        assert_eq!(measurement.value_unit.as_deref(), Some("nF"));
+        assert!((measurement.base_value().unwrap() - 100e-9).abs() < 1e-18);
+        let frame = Measurement::encode(3, "0025", &[DisplayIcon::DegF]).unwrap();
+        assert_eq!(
+            Measurement::from_bytes(&frame).unwrap().resolution(),
+            Some(1.0)
+        );
+        // Temperatures are in °C whatever the meter shows
+        let frame = Measurement::encode(3, "0212", &[DisplayIcon::DegF]).unwrap();
+        assert_eq!(
+            Measurement::from_bytes(&frame).unwrap().base_value(),
+            Some(100.0)
+        );
+        let frame = Measurement::encode(3, "0025", &[DisplayIcon::DegC]).unwrap();
+        assert_eq!(
+            Measurement::from_bytes(&frame).unwrap().base_value(),
+            Some(25.0)
+        );
+        let frame = Measurement::encode(3, " .0L ", &[DisplayIcon::Volt]).unwrap();
+        assert_eq!(Measurement::from_bytes(&frame).unwrap().value(), None);
+        assert_eq!(Measurement::from_bytes(&frame).unwrap().resolution(), None);
+        assert!(Measurement::from_bytes(&frame).unwrap().is_overload());
+        assert_eq!(
+            Measurement::from_bytes(&frame)
+                .unwrap()
+                .uncertainty(&DMM_3_PROFILE),
+            None
+        );
+        assert!(!measurement.is_overload());
+        // 4% + 10 digits of 100.0 nF
+        let uncertainty = measurement.uncertainty(&DMM_3_PROFILE).unwrap();
+        assert!((uncertainty.margin - 5.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_encode() {
+        let frames: [[u8; 11]; 5] = [
+            [27, 132, 112, 177, 140, 162, 23, 118, 102, 170, 59],
+            [27, 132, 112, 177, 89, 42, 217, 122, 102, 250, 58],
+            [27, 132, 112, 161, 105, 30, 181, 123, 102, 250, 58],
+            [27, 132, 112, 89, 82, 170, 51, 241, 102, 186, 59],
+            [27, 132, 112, 161, 73, 154, 188, 126, 102, 218, 58],
+        ];
+        for frame in frames {
+            let measurement = Measurement::from_bytes(&frame).unwrap();
+            assert_eq!(measurement.to_bytes().unwrap(), frame);
+
+            let text = match &measurement.displayed_value {
+                DisplayValue::Number(_) => measurement.display_segments.iter().enumerate().fold(
+                    String::new(),
+                    |mut text, (i, s)| {
+                        if s.get_dot_dash() {
+                            text.push(if i == 0 { '-' } else { '.' });
+                        }
+                        text.push(s.get_text().unwrap());
+                        text
+                    },
+                ),
+                DisplayValue::Text(text) => text.clone(),
+            };
+            let encoded = Measurement::encode(3, &text, &measurement.displayed_icons).unwrap();
+            assert_eq!(encoded, frame);
+        }
+
+        let frame =
+            Measurement::encode(3, "-12.34", &[DisplayIcon::Volt, DisplayIcon::AC]).unwrap();
+        let measurement = Measurement::from_bytes(&frame).unwrap();
+        assert_eq!(measurement.displayed_value, DisplayValue::Number(-12.34));
+        assert_eq!(
+            measurement.displayed_icons,
+            vec![DisplayIcon::AC, DisplayIcon::Volt]
+        );
+
+        assert!(Measurement::encode(3, "12.3", &[]).is_err());
+        assert!(Measurement::encode(3, "1..23", &[]).is_err());
+        assert!(Measurement::encode(3, "12?4", &[]).is_err());
+        assert!(Measurement::encode(1, "1234", &[DisplayIcon::LowBattery]).is_err());
+    }
 }
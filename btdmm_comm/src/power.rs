@@ -0,0 +1,297 @@
+use std::error::Error;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use futures::{Stream, StreamExt, stream};
+
+use crate::device::MeasurementStream;
+use crate::parser::Mode;
+use crate::timed::TimedMeasurement;
+
+pub type PowerStream<'a> = Pin<Box<dyn Stream<Item = Result<PowerSample, Box<dyn Error>>> + 'a>>;
+
+/// Power computed from a pair of voltage and current readings, with the
+/// totals since the meter was started.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PowerSample {
+    pub timestamp: DateTime<Utc>,
+    /// In volts.
+    pub voltage: f64,
+    /// In amperes.
+    pub current: f64,
+    /// In watts.
+    pub power: f64,
+    /// In watt-hours.
+    pub energy: f64,
+    /// In ampere-hours.
+    pub charge: f64,
+    /// When the voltage reading of the sample was received.
+    pub voltage_timestamp: DateTime<Utc>,
+    /// When the current reading of the sample was received.
+    pub current_timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Reading {
+    value: f64,
+    received: Instant,
+    timestamp: DateTime<Utc>,
+}
+
+/// Computes power, energy and charge from two meters, one measuring the
+/// voltage and the other the current.
+///
+/// Readings are converted to volts and amperes, so the meters may switch
+/// ranges freely. Each new reading is paired with the latest one of the other
+/// meter if they were received close enough; energy and charge are
+/// integrated with the trapezoidal rule between the paired samples.
+#[derive(Debug)]
+pub struct PowerMeter {
+    max_skew: Duration,
+    max_gap: Duration,
+    voltage: Option<Reading>,
+    current: Option<Reading>,
+    last: Option<(Instant, f64, f64)>,
+    /// In joules.
+    energy: f64,
+    /// In coulombs.
+    charge: f64,
+}
+
+impl Default for PowerMeter {
+    fn default() -> PowerMeter {
+        PowerMeter {
+            max_skew: Duration::from_secs(1),
+            max_gap: Duration::from_secs(5),
+            voltage: None,
+            current: None,
+            last: None,
+            energy: 0.0,
+            charge: 0.0,
+        }
+    }
+}
+
+impl PowerMeter {
+    pub fn new() -> PowerMeter {
+        PowerMeter::default()
+    }
+
+    /// Longest time between a voltage and a current reading to pair them
+    /// (one second by default).
+    pub fn max_skew(mut self, max_skew: Duration) -> Self {
+        self.max_skew = max_skew;
+        self
+    }
+
+    /// Longest time between two samples to integrate over (five seconds by
+    /// default); longer gaps don't add to the totals.
+    pub fn max_gap(mut self, max_gap: Duration) -> Self {
+        self.max_gap = max_gap;
+        self
+    }
+
+    /// Feeds a reading of the voltage meter. Readings in other modes, and
+    /// overloads, leave the power unknown until the next voltage reading.
+    pub fn push_voltage(&mut self, reading: &TimedMeasurement) -> Option<PowerSample> {
+        let modes = [Mode::DcVoltage, Mode::AcVoltage];
+        self.voltage = channel_reading(reading, &modes);
+        self.sample()
+    }
+
+    /// Feeds a reading of the current meter. Readings in other modes, and
+    /// overloads, leave the power unknown until the next current reading.
+    pub fn push_current(&mut self, reading: &TimedMeasurement) -> Option<PowerSample> {
+        let modes = [Mode::DcCurrent, Mode::AcCurrent];
+        self.current = channel_reading(reading, &modes);
+        self.sample()
+    }
+
+    /// Feeds a reading of either meter, telling them apart by their mode.
+    pub fn push(&mut self, reading: &TimedMeasurement) -> Option<PowerSample> {
+        match reading.measurement.mode()? {
+            Mode::DcVoltage | Mode::AcVoltage => self.push_voltage(reading),
+            Mode::DcCurrent | Mode::AcCurrent => self.push_current(reading),
+            _ => None,
+        }
+    }
+
+    /// Energy since the start, in watt-hours.
+    pub fn energy(&self) -> f64 {
+        self.energy / 3600.0
+    }
+
+    /// Charge since the start, in ampere-hours.
+    pub fn charge(&self) -> f64 {
+        self.charge / 3600.0
+    }
+
+    /// Zeroes the totals.
+    pub fn reset(&mut self) {
+        self.last = None;
+        self.energy = 0.0;
+        self.charge = 0.0;
+    }
+
+    /// Pairs the readings of two meters into a stream of power samples.
+    /// Errors of either meter are passed through.
+    pub fn apply<'a>(
+        self,
+        voltage: MeasurementStream<'a>,
+        current: MeasurementStream<'a>,
+    ) -> PowerStream<'a> {
+        let readings = stream::select(voltage.map(|r| (true, r)), current.map(|r| (false, r)));
+        Box::pin(
+            readings
+                .scan(self, |meter, (is_voltage, reading)| {
+                    let sample = match reading {
+                        Ok(reading) if is_voltage => meter.push_voltage(&reading).map(Ok),
+                        Ok(reading) => meter.push_current(&reading).map(Ok),
+                        Err(err) => Some(Err(err)),
+                    };
+                    futures::future::ready(Some(sample))
+                })
+                .filter_map(futures::future::ready),
+        )
+    }
+
+    fn sample(&mut self) -> Option<PowerSample> {
+        let (voltage, current) = (self.voltage?, self.current?);
+        let skew = if voltage.received > current.received {
+            voltage.received - current.received
+        } else {
+            current.received - voltage.received
+        };
+        if skew > self.max_skew {
+            return None;
+        }
+
+        let received = voltage.received.max(current.received);
+        let power = voltage.value * current.value;
+        if let Some((last, last_power, last_current)) = self.last {
+            let elapsed = received.saturating_duration_since(last);
+            if elapsed <= self.max_gap {
+                let seconds = elapsed.as_secs_f64();
+                self.energy += (last_power + power) / 2.0 * seconds;
+                self.charge += (last_current + current.value) / 2.0 * seconds;
+            }
+        }
+        self.last = Some((received, power, current.value));
+
+        Some(PowerSample {
+            timestamp: voltage.timestamp.max(current.timestamp),
+            voltage: voltage.value,
+            current: current.value,
+            power,
+            energy: self.energy(),
+            charge: self.charge(),
+            voltage_timestamp: voltage.timestamp,
+            current_timestamp: current.timestamp,
+        })
+    }
+}
+
+fn channel_reading(reading: &TimedMeasurement, modes: &[Mode]) -> Option<Reading> {
+    let measurement = &reading.measurement;
+    if !measurement.mode().is_some_and(|mode| modes.contains(&mode)) {
+        return None;
+    }
+    Some(Reading {
+        value: measurement.base_value()?,
+        received: reading.received,
+        timestamp: reading.timestamp,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::DisplayIcon;
+    use crate::timed::reading;
+
+    #[test]
+    fn test_power() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let volts = |text, ms| TimedMeasurement {
+            received: at(ms),
+            ..reading(text, &[DisplayIcon::Volt, DisplayIcon::DC])
+        };
+        let milliamps = |text, ms| TimedMeasurement {
+            received: at(ms),
+            ..reading(text, &[DisplayIcon::MilliAmpere, DisplayIcon::DC])
+        };
+        let amps = |text, ms| TimedMeasurement {
+            received: at(ms),
+            ..reading(text, &[DisplayIcon::Ampere, DisplayIcon::DC])
+        };
+
+        let mut meter = PowerMeter::new().max_skew(Duration::from_secs(2));
+        assert!(meter.push_voltage(&volts("12.00", 0)).is_none());
+        let sample = meter.push_current(&milliamps("500.0", 100)).unwrap();
+        assert!((sample.power - 6.0).abs() < 1e-9);
+        assert_eq!(sample.energy, 0.0);
+
+        // The current meter switches to the A range
+        let sample = meter.push_current(&amps("1.000", 1100)).unwrap();
+        assert!((sample.current - 1.0).abs() < 1e-9);
+        assert!((sample.power - 12.0).abs() < 1e-9);
+        // 9 W on average for one second
+        assert!((meter.energy() * 3600.0 - 9.0).abs() < 1e-9);
+        assert!((meter.charge() * 3600.0 - 0.75).abs() < 1e-9);
+
+        // Too far from the last current reading
+        assert!(meter.push_voltage(&volts("11.00", 3500)).is_none());
+        // Overloads and other modes are not used
+        assert!(meter.push_current(&amps(" .0L ", 2600)).is_none());
+        assert!(
+            meter
+                .push_current(&TimedMeasurement {
+                    received: at(2700),
+                    ..reading("1.000", &[DisplayIcon::Volt])
+                })
+                .is_none()
+        );
+
+        // A long gap isn't integrated
+        assert!(meter.push_current(&amps("1.000", 10000)).is_none());
+        let sample = meter.push_voltage(&volts("11.00", 10100)).unwrap();
+        assert!((sample.power - 11.0).abs() < 1e-9);
+        assert!(meter.push(&volts("11.00", 10200)).is_some());
+        assert!(
+            meter
+                .push(&TimedMeasurement {
+                    received: at(10300),
+                    ..reading("4.700", &[DisplayIcon::KiloOhm])
+                })
+                .is_none()
+        );
+        assert!((sample.energy * 3600.0 - 9.0).abs() < 1e-9);
+
+        meter.reset();
+        assert_eq!(meter.energy(), 0.0);
+    }
+
+    #[test]
+    fn test_power_stream() {
+        let voltage: MeasurementStream = Box::pin(stream::iter(vec![Ok(reading(
+            "5.000",
+            &[DisplayIcon::Volt],
+        ))]));
+        let current: MeasurementStream = Box::pin(stream::iter(vec![
+            Ok(reading("2.000", &[DisplayIcon::Ampere])),
+            Err("lost".into()),
+        ]));
+
+        let samples: Vec<_> =
+            async_std::task::block_on(PowerMeter::new().apply(voltage, current).collect());
+        let powers: Vec<_> = samples
+            .iter()
+            .filter_map(|s| s.as_ref().ok())
+            .map(|s| s.power)
+            .collect();
+        assert_eq!(powers, [10.0]);
+        assert_eq!(samples.iter().filter(|s| s.is_err()).count(), 1);
+    }
+}
@@ -0,0 +1,80 @@
+//! Per-model knowledge about the supported meters.
+
+use uuid::Uuid;
+
+use crate::accuracy::{self, Accuracy, AccuracySpec, TYPICAL_ACCURACY};
+use crate::parser::Mode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Button {
+    Hold,
+    Range,
+    Select,
+    Relative,
+    MinMax,
+    Backlight,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Command {
+    Press(Button),
+    /// Selects a manual range, `0` being the most sensitive one.
+    SetRange(u8),
+    AutoRange,
+}
+
+#[derive(Debug)]
+pub struct ModelProfile {
+    pub name: &'static str,
+    pub dmm_id: u8,
+    /// Characteristic of the FFF0 service accepting commands, if any. None of the
+    /// built-in profiles has one yet, so `DmmDevice::send` returns
+    /// `DmmError::Unsupported` for them.
+    pub command_characteristic: Option<Uuid>,
+    /// Returns the bytes to write for a command, or `None` if the meter can't do it.
+    pub encode_command: fn(Command) -> Option<Vec<u8>>,
+    /// Accuracy by mode and range, the first matching spec applying.
+    pub accuracy: &'static [AccuracySpec],
+}
+
+impl ModelProfile {
+    /// Returns the accuracy of the range showing `unit` in `mode`, if known.
+    pub fn accuracy_for(&self, mode: Mode, unit: Option<&str>) -> Option<Accuracy> {
+        accuracy::lookup(self.accuracy, mode, unit)
+    }
+
+    pub fn supports(&self, command: Command) -> bool {
+        self.command_characteristic.is_some() && (self.encode_command)(command).is_some()
+    }
+}
+
+fn no_commands(_: Command) -> Option<Vec<u8>> {
+    None
+}
+
+// The remote control protocol of the known models hasn't been figured out yet,
+// so they are treated as read-only.
+pub static DMM_1_PROFILE: ModelProfile = ModelProfile {
+    name: "DMM type 1",
+    dmm_id: 1,
+    command_characteristic: None,
+    encode_command: no_commands,
+    accuracy: &TYPICAL_ACCURACY,
+};
+
+pub static DMM_3_PROFILE: ModelProfile = ModelProfile {
+    name: "DMM type 3",
+    dmm_id: 3,
+    command_characteristic: None,
+    encode_command: no_commands,
+    accuracy: &TYPICAL_ACCURACY,
+};
+
+/// Returns the built-in profile matching the id reported in the frames.
+pub fn profile_for(dmm_id: u8) -> &'static ModelProfile {
+    if dmm_id == 3 {
+        &DMM_3_PROFILE
+    } else {
+        &DMM_1_PROFILE
+    }
+}
@@ -0,0 +1,159 @@
+use std::fmt;
+
+use crate::units::format_si;
+use crate::parser::Mode;
+use crate::timed::TimedMeasurement;
+
+/// Difference between a reading and the reference of a `RelativeMode`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RelativeReading {
+    pub mode: Mode,
+    /// In the base unit of the mode.
+    pub value: f64,
+    /// In the base unit of the mode.
+    pub reference: f64,
+    /// `value - reference`, in the base unit of the mode.
+    pub delta: f64,
+    /// Delta relative to the reference, unless it is zero.
+    pub percent: Option<f64>,
+}
+
+impl fmt::Display for RelativeReading {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.delta > 0.0 { "+" } else { "" };
+        write!(f, "Δ {}{}", sign, format_si(self.delta, self.mode.unit()))?;
+        if let Some(percent) = self.percent {
+            write!(f, " ({:+.2}%)", percent)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Reference {
+    /// The next reading becomes the reference.
+    Next,
+    Value(Mode, f64),
+}
+
+/// Software version of the relative (Δ) function of the meters: readings are
+/// reported as differences from a reference, captured from a reading or set
+/// explicitly.
+///
+/// Values are compared in the base unit of the mode, so the meter may change
+/// ranges; the reference is dropped when the mode changes.
+#[derive(Debug, Clone, Default)]
+pub struct RelativeMode {
+    reference: Option<Reference>,
+}
+
+impl RelativeMode {
+    pub fn new() -> RelativeMode {
+        RelativeMode::default()
+    }
+
+    /// Takes the next reading with a number as the reference.
+    pub fn zero(&mut self) {
+        self.reference = Some(Reference::Next);
+    }
+
+    /// Takes `reading` as the reference, returning whether it has a number.
+    pub fn capture(&mut self, reading: &TimedMeasurement) -> bool {
+        let measurement = &reading.measurement;
+        match (measurement.mode(), measurement.base_value()) {
+            (Some(mode), Some(value)) => {
+                self.reference = Some(Reference::Value(mode, value));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Sets the reference of `mode`, in its base unit.
+    pub fn set(&mut self, mode: Mode, value: f64) {
+        self.reference = Some(Reference::Value(mode, value));
+    }
+
+    pub fn clear(&mut self) {
+        self.reference = None;
+    }
+
+    /// Returns the mode and value of the reference, once it is known.
+    pub fn reference(&self) -> Option<(Mode, f64)> {
+        match self.reference? {
+            Reference::Value(mode, value) => Some((mode, value)),
+            Reference::Next => None,
+        }
+    }
+
+    /// Feeds the next reading, returning its difference from the reference.
+    ///
+    /// A reading in another mode than the reference clears it; overloads and
+    /// readings without a number leave it as it is.
+    pub fn push(&mut self, reading: &TimedMeasurement) -> Option<RelativeReading> {
+        if self.reference == Some(Reference::Next) {
+            self.capture(reading);
+        }
+        let (mode, reference) = self.reference()?;
+
+        let measurement = &reading.measurement;
+        if measurement.mode() != Some(mode) {
+            self.reference = None;
+            return None;
+        }
+        let value = measurement.base_value()?;
+
+        let delta = value - reference;
+        Some(RelativeReading {
+            mode,
+            value,
+            reference,
+            delta,
+            percent: (reference != 0.0).then(|| delta / reference.abs() * 100.0),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::DisplayIcon;
+    use crate::timed::reading;
+
+    #[test]
+    fn test_relative() {
+        let volts = |text| reading(text, &[DisplayIcon::Volt, DisplayIcon::DC]);
+        let millivolts = |text| reading(text, &[DisplayIcon::MilliVolt, DisplayIcon::DC]);
+
+        let mut relative = RelativeMode::new();
+        assert!(relative.push(&volts("5.000")).is_none());
+
+        relative.zero();
+        assert!(relative.push(&volts(" .0L ")).is_none());
+        let zero = relative.push(&volts("5.000")).unwrap();
+        assert_eq!(zero.delta, 0.0);
+        assert_eq!(relative.reference(), Some((Mode::DcVoltage, 5.0)));
+
+        let result = relative.push(&volts("5.025")).unwrap();
+        assert!((result.delta - 0.025).abs() < 1e-9);
+        assert!((result.percent.unwrap() - 0.5).abs() < 1e-9);
+        assert_eq!(result.to_string(), "Δ +25 mV (+0.50%)");
+
+        // Range changes keep the reference
+        relative.set(Mode::DcVoltage, 0.5);
+        let result = relative.push(&millivolts("498.0")).unwrap();
+        assert_eq!(result.to_string(), "Δ -2 mV (-0.40%)");
+
+        // Mode changes drop it
+        assert!(
+            relative
+                .push(&reading("1.000", &[DisplayIcon::KiloOhm]))
+                .is_none()
+        );
+        assert_eq!(relative.reference(), None);
+        assert!(relative.push(&volts("5.000")).is_none());
+
+        relative.set(Mode::DcVoltage, 0.0);
+        assert_eq!(relative.push(&volts("0.010")).unwrap().percent, None);
+    }
+}
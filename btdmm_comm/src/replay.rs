@@ -0,0 +1,346 @@
+//! Playback of recorded frames through the `Transport` interface.
+//!
+//! Captures are plain text, one frame per line: the time in seconds since the
+//! start of the recording followed by the raw frame bytes in hex, exactly as
+//! they were received from the meter. Blank lines and lines starting with `#`
+//! are ignored.
+//!
+//! ```text
+//! # btdmm capture
+//! 0.000 1b8470b1592ad97a66fa3a
+//! 0.512 1b8470a1691eb57b66fa3a
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use btleplug::api::ValueNotification;
+use futures::stream;
+
+use crate::DmmError;
+use crate::device::DMM_CHARACTERISTIC_UUID;
+use crate::transport::{NotificationStream, Transport};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapturedFrame {
+    /// Time since the start of the capture.
+    pub offset: Duration,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Capture {
+    frames: Vec<CapturedFrame>,
+}
+
+impl Capture {
+    pub fn new() -> Capture {
+        Capture::default()
+    }
+
+    pub fn frames(&self) -> &[CapturedFrame] {
+        &self.frames
+    }
+
+    pub fn push(&mut self, offset: Duration, data: Vec<u8>) {
+        self.frames.push(CapturedFrame { offset, data });
+    }
+
+    pub fn parse(text: &str) -> Result<Capture, DmmError> {
+        let mut capture = Capture::new();
+
+        for (lineno, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid =
+                |what: &str| DmmError::InvalidCapture(format!("line {}: {}", lineno + 1, what));
+
+            let (offset, data) = line
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| invalid("expected a timestamp and a frame"))?;
+            let offset = offset
+                .parse::<f64>()
+                .ok()
+                .and_then(|s| Duration::try_from_secs_f64(s).ok())
+                .ok_or_else(|| invalid("invalid timestamp"))?;
+            let data = parse_hex(data.trim()).ok_or_else(|| invalid("invalid frame data"))?;
+
+            if let Some(last) = capture.frames.last() {
+                if offset < last.offset {
+                    return Err(invalid("timestamps must not go backwards"));
+                }
+            }
+
+            capture.push(offset, data);
+        }
+
+        Ok(capture)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Capture, Box<dyn Error>> {
+        Ok(Capture::parse(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+
+    /// Time between the first and the last frame.
+    pub fn duration(&self) -> Duration {
+        match (self.frames.first(), self.frames.last()) {
+            (Some(first), Some(last)) => last.offset - first.offset,
+            _ => Duration::ZERO,
+        }
+    }
+}
+
+impl fmt::Display for Capture {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "# btdmm capture")?;
+        for frame in &self.frames {
+            write!(f, "{:.3} ", frame.offset.as_secs_f64())?;
+            for byte in &frame.data {
+                write!(f, "{:02x}", byte)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_hex(text: &str) -> Option<Vec<u8>> {
+    if text.is_empty() || !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[derive(Debug)]
+struct ReplayState {
+    /// When the first frame of the current pass is due.
+    epoch: Instant,
+    next: usize,
+}
+
+/// Transport that plays back a `Capture` as if it came from a connected meter.
+///
+/// Frames are delivered with their original spacing, divided by the playback
+/// speed. Once the capture is exhausted the notification stream ends, which
+/// `DmmDevice` reports as a disconnection, unless looping is enabled.
+#[derive(Debug, Clone)]
+pub struct ReplayTransport {
+    capture: Arc<Capture>,
+    speed: f64,
+    looping: bool,
+    state: Arc<Mutex<Option<ReplayState>>>,
+}
+
+impl ReplayTransport {
+    pub fn new(capture: Capture) -> ReplayTransport {
+        ReplayTransport {
+            capture: Arc::new(capture),
+            speed: 1.0,
+            looping: false,
+            state: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Sets the playback speed, e.g. `10.0` plays ten times faster than recorded.
+    pub fn speed(mut self, speed: f64) -> Result<ReplayTransport, DmmError> {
+        if !(speed > 0.0 && speed.is_finite()) {
+            return Err(DmmError::InvalidPlayback(format!(
+                "the speed must be positive, got {}",
+                speed
+            )));
+        }
+        self.speed = speed;
+        Ok(self)
+    }
+
+    /// Starts over from the first frame when the end of the capture is reached.
+    pub fn looping(mut self, looping: bool) -> ReplayTransport {
+        self.looping = looping;
+        self
+    }
+
+    pub fn capture(&self) -> &Capture {
+        &self.capture
+    }
+
+    /// Pops the next frame together with the instant it is due, or `None` if
+    /// playback is over.
+    fn next_frame(&self) -> Option<(Instant, Vec<u8>)> {
+        let mut state = self.state.lock().unwrap();
+        let state = state.as_mut()?;
+        let frames = self.capture.frames();
+        let first = frames.first()?;
+
+        if state.next == frames.len() {
+            if !self.looping {
+                return None;
+            }
+            // Leave the average frame interval between the end and the restart,
+            // or a second if the frames all have the same time
+            let duration = self.capture.duration();
+            let gap = if duration.is_zero() {
+                Duration::from_secs(1)
+            } else {
+                duration / (frames.len() - 1) as u32
+            };
+            state.epoch += (duration + gap).div_f64(self.speed);
+            state.next = 0;
+        }
+
+        let frame = &frames[state.next];
+        let due = state.epoch + (frame.offset - first.offset).div_f64(self.speed);
+        state.next += 1;
+        Some((due, frame.data.clone()))
+    }
+}
+
+#[async_trait]
+impl Transport for ReplayTransport {
+    async fn connect(&self) -> Result<(), Box<dyn Error>> {
+        *self.state.lock().unwrap() = Some(ReplayState {
+            epoch: Instant::now(),
+            next: 0,
+        });
+        Ok(())
+    }
+
+    async fn disconnect(&self) -> Result<(), Box<dyn Error>> {
+        *self.state.lock().unwrap() = None;
+        Ok(())
+    }
+
+    async fn notifications(&self) -> Result<NotificationStream, Box<dyn Error>> {
+        if self.state.lock().unwrap().is_none() {
+            return Err(DmmError::NotConnected.into());
+        }
+
+        let replay = self.clone();
+        Ok(Box::pin(stream::unfold(replay, |replay| async move {
+            let (due, data) = replay.next_frame()?;
+            let now = Instant::now();
+            if due > now {
+                async_std::task::sleep(due - now).await;
+            }
+            let notification = ValueNotification {
+                uuid: DMM_CHARACTERISTIC_UUID,
+                value: data,
+            };
+            Some((notification, replay))
+        })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DisplayValue, DmmDevice};
+
+    const CAPTURE: &str = "
+        # btdmm capture
+        0.000 1b8470b1592ad97a66fa3a
+        0.500 1b8470a1691eb57b66fa3a
+    ";
+
+    #[test]
+    fn test_parse_capture() {
+        let capture = Capture::parse(CAPTURE).unwrap();
+        assert_eq!(capture.frames().len(), 2);
+        assert_eq!(capture.frames()[1].offset, Duration::from_millis(500));
+        assert_eq!(
+            capture.frames()[0].data,
+            vec![27, 132, 112, 177, 89, 42, 217, 122, 102, 250, 58]
+        );
+        assert_eq!(Capture::parse(&capture.to_string()).unwrap(), capture);
+
+        assert!(Capture::parse("0.0 1b84z0").is_err());
+        assert!(Capture::parse("1.0 1b84\n0.5 1b84").is_err());
+        assert!(Capture::parse("1b8470").is_err());
+    }
+
+    #[test]
+    fn test_replay() {
+        let capture = Capture::parse(CAPTURE).unwrap();
+        let dmm = DmmDevice::new(ReplayTransport::new(capture).speed(100.0).unwrap());
+        let timeout = Duration::from_secs(1);
+
+        async_std::task::block_on(async {
+            assert!(dmm.next_event(timeout).await.is_err());
+            dmm.connect().await.unwrap();
+
+            let measurement = dmm.next_event(timeout).await.unwrap();
+            assert_eq!(measurement.displayed_value, DisplayValue::Number(0.0));
+            let measurement = dmm.next_event(timeout).await.unwrap();
+            assert_eq!(measurement.displayed_value, DisplayValue::Number(-9.57));
+
+            let err = dmm.next_event(timeout).await.unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<DmmError>(),
+                Some(DmmError::DeviceDisconnected)
+            ));
+        });
+
+        let capture = Capture::parse(CAPTURE).unwrap();
+        assert!(ReplayTransport::new(capture.clone()).speed(-1.0).is_err());
+        assert!(ReplayTransport::new(capture).speed(f64::NAN).is_err());
+    }
+
+    #[test]
+    fn test_replay_looping() {
+        let capture = Capture::parse(CAPTURE).unwrap();
+        let dmm = DmmDevice::new(
+            ReplayTransport::new(capture)
+                .speed(100.0)
+                .unwrap()
+                .looping(true),
+        );
+        let timeout = Duration::from_secs(1);
+
+        async_std::task::block_on(async {
+            // Playback starts when connecting
+            let start = Instant::now();
+            dmm.connect().await.unwrap();
+            let values = [0.0, -9.57, 0.0, -9.57, 0.0];
+            for value in values {
+                let measurement = dmm.next_event(timeout).await.unwrap();
+                assert_eq!(measurement.displayed_value, DisplayValue::Number(value));
+            }
+            // 4 intervals of 500 ms at 100x speed
+            assert!(start.elapsed() >= Duration::from_millis(20));
+        });
+
+        // Frames all received at once are looped a second apart
+        let capture =
+            Capture::parse("0.0 1b8470b1592ad97a66fa3a\n0.0 1b8470a1691eb57b66fa3a").unwrap();
+        let dmm = DmmDevice::new(
+            ReplayTransport::new(capture)
+                .speed(100.0)
+                .unwrap()
+                .looping(true),
+        );
+        async_std::task::block_on(async {
+            // Playback starts when connecting
+            let start = Instant::now();
+            dmm.connect().await.unwrap();
+            for _ in 0..5 {
+                dmm.next_event(timeout).await.unwrap();
+            }
+            assert!(start.elapsed() >= Duration::from_millis(20));
+        });
+    }
+}
@@ -0,0 +1,247 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::DmmError;
+use crate::parser::Mode;
+use crate::timed::TimedMeasurement;
+
+/// How far apart readings may be and still count as the same value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tolerance {
+    /// Counts of the last displayed digit.
+    Digits(u32),
+    /// Percentage of the reading.
+    Percent(f64),
+}
+
+impl FromStr for Tolerance {
+    type Err = DmmError;
+
+    /// Parses `5d` (or a bare `5`) as digits and `0.5%` as a percentage.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || DmmError::InvalidTolerance(s.to_string());
+        let s = s.trim();
+        if let Some(percent) = s.strip_suffix('%') {
+            match percent.trim().parse::<f64>() {
+                Ok(percent) if percent >= 0.0 => Ok(Tolerance::Percent(percent)),
+                _ => Err(invalid()),
+            }
+        } else {
+            let digits = s.strip_suffix('d').unwrap_or(s);
+            digits
+                .trim()
+                .parse()
+                .map(Tolerance::Digits)
+                .map_err(|_| invalid())
+        }
+    }
+}
+
+impl fmt::Display for Tolerance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Tolerance::Digits(digits) => write!(f, "{}d", digits),
+            Tolerance::Percent(percent) => write!(f, "{}%", percent),
+        }
+    }
+}
+
+/// Reading that has stayed put long enough to be trusted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StableReading {
+    /// The reading that completed the stable run.
+    pub reading: TimedMeasurement,
+    /// Mean of the readings of the run.
+    pub mean: f64,
+    pub unit: Option<String>,
+    pub readings: usize,
+}
+
+type StableHook = Box<dyn FnMut(&StableReading) + Send>;
+
+#[derive(Debug)]
+struct Run {
+    mode: Option<Mode>,
+    unit: Option<String>,
+    readings: usize,
+    min: f64,
+    max: f64,
+    sum: f64,
+    resolution: f64,
+    reported: bool,
+}
+
+/// Watches a measurement stream for readings that settle, like the auto-hold
+/// of some meters.
+///
+/// A run starts over whenever a reading falls out of tolerance of the others,
+/// the mode or unit changes or no number is displayed. Each run is reported
+/// once, when it reaches the required number of readings.
+pub struct SettleDetector {
+    readings: usize,
+    tolerance: Tolerance,
+    run: Option<Run>,
+    on_stable: Option<StableHook>,
+}
+
+impl SettleDetector {
+    /// Considers the value settled after `readings` consecutive readings in
+    /// tolerance.
+    pub fn new(readings: usize, tolerance: Tolerance) -> SettleDetector {
+        SettleDetector {
+            readings: readings.max(1),
+            tolerance,
+            run: None,
+            on_stable: None,
+        }
+    }
+
+    /// Calls `hook` on every stable reading, for instance to beep.
+    pub fn on_stable(mut self, hook: impl FnMut(&StableReading) + Send + 'static) -> Self {
+        self.on_stable = Some(Box::new(hook));
+        self
+    }
+
+    pub fn tolerance(&self) -> Tolerance {
+        self.tolerance
+    }
+
+    /// Feeds the next reading, returning it when it completes a stable run.
+    pub fn push(&mut self, timed: &TimedMeasurement) -> Option<StableReading> {
+        let measurement = &timed.measurement;
+        let (Some(value), Some(resolution)) = (measurement.value(), measurement.resolution())
+        else {
+            self.run = None;
+            return None;
+        };
+        let mode = measurement.mode();
+
+        let fits = self.run.as_ref().is_some_and(|run| {
+            let min = run.min.min(value);
+            let max = run.max.max(value);
+            let allowed = match self.tolerance {
+                Tolerance::Digits(digits) => digits as f64 * run.resolution.max(resolution),
+                Tolerance::Percent(percent) => percent / 100.0 * min.abs().max(max.abs()),
+            };
+            run.mode == mode
+                && run.unit == measurement.value_unit
+                // Leave some room for the rounding of the display
+                && max - min <= allowed + resolution * 1e-6
+        });
+
+        let run = match &mut self.run {
+            Some(run) if fits => {
+                run.readings += 1;
+                run.min = run.min.min(value);
+                run.max = run.max.max(value);
+                run.sum += value;
+                run.resolution = run.resolution.max(resolution);
+                run
+            }
+            _ => self.run.insert(Run {
+                mode,
+                unit: measurement.value_unit.clone(),
+                readings: 1,
+                min: value,
+                max: value,
+                sum: value,
+                resolution,
+                reported: false,
+            }),
+        };
+
+        if run.reported || run.readings < self.readings {
+            return None;
+        }
+        run.reported = true;
+
+        let stable = StableReading {
+            reading: timed.clone(),
+            mean: run.sum / run.readings as f64,
+            unit: run.unit.clone(),
+            readings: run.readings,
+        };
+        if let Some(hook) = &mut self.on_stable {
+            hook(&stable);
+        }
+        Some(stable)
+    }
+
+    /// Forgets the current run, so the next settled value is reported even if
+    /// it is the same.
+    pub fn reset(&mut self) {
+        self.run = None;
+    }
+}
+
+impl fmt::Debug for SettleDetector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SettleDetector")
+            .field("readings", &self.readings)
+            .field("tolerance", &self.tolerance)
+            .field("run", &self.run)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::parser::DisplayIcon;
+    use crate::timed::reading;
+
+    #[test]
+    fn test_tolerance() {
+        assert_eq!("3d".parse::<Tolerance>().unwrap(), Tolerance::Digits(3));
+        assert_eq!("3".parse::<Tolerance>().unwrap(), Tolerance::Digits(3));
+        assert_eq!(
+            "0.5%".parse::<Tolerance>().unwrap(),
+            Tolerance::Percent(0.5)
+        );
+        assert!("-1%".parse::<Tolerance>().is_err());
+        assert!("fast".parse::<Tolerance>().is_err());
+        assert_eq!(Tolerance::Percent(0.5).to_string(), "0.5%");
+    }
+
+    #[test]
+    fn test_settle() {
+        let volts = |text| reading(text, &[DisplayIcon::Volt, DisplayIcon::DC]);
+        let beeps = Arc::new(AtomicUsize::new(0));
+        let counter = beeps.clone();
+        let mut detector = SettleDetector::new(3, Tolerance::Digits(2)).on_stable(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+
+        let texts = [
+            "0.000", "1.200", "2.950", "3.301", "3.300", "3.302", "3.301", "3.299", " .0L ",
+            "3.300", "3.301", "3.300",
+        ];
+        let stable: Vec<_> = texts
+            .iter()
+            .enumerate()
+            .filter_map(|(i, text)| {
+                detector.push(&TimedMeasurement {
+                    sequence: i as u64,
+                    ..volts(text)
+                })
+            })
+            .collect();
+
+        // Once when the probe settles, not again while it stays, then after the overload
+        assert_eq!(stable.len(), 2);
+        assert_eq!(stable[0].reading.sequence, 5);
+        assert_eq!(stable[0].readings, 3);
+        assert!((stable[0].mean - 3.301).abs() < 1e-9);
+        assert_eq!(stable[0].unit.as_deref(), Some("V"));
+        assert_eq!(stable[1].reading.sequence, 11);
+        assert_eq!(beeps.load(Ordering::SeqCst), 2);
+
+        let mut detector = SettleDetector::new(2, Tolerance::Percent(1.0));
+        assert!(detector.push(&volts("5.000")).is_none());
+        assert!(detector.push(&volts("5.100")).is_none());
+        assert!(detector.push(&volts("5.140")).is_some());
+    }
+}
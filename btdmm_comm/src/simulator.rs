@@ -0,0 +1,627 @@
+//! Virtual meter that generates frames from a scenario description.
+//!
+//! A scenario is a text file with one step per line; blank lines and lines
+//! starting with `#` are ignored:
+//!
+//! ```text
+//! dc-volts ramp 0 12 for 30s noise 0.05
+//! dc-volts overload for 2s
+//! ohms const 4.7k for 10s hold
+//! wait 5s
+//! disconnect
+//! continuity const 0.3 for 3s
+//! ```
+//!
+//! Measuring steps are `<function> const <value> for <duration>`,
+//! `<function> ramp <from> <to> for <duration>` and
+//! `<function> overload for <duration>`, optionally followed by
+//! `noise <amplitude>` and the flags `hold`, `relative`, `low-battery` and
+//! `manual` (no auto-ranging). Values are in the base unit of the function and
+//! accept the `n`, `u`, `m`, `k` and `M` suffixes. `wait` stops sending frames
+//! for a while and `disconnect` drops the link; connecting again resumes the
+//! scenario with the following step.
+//!
+//! Frames are encoded with the DMM 3 icon table, so they exercise the same
+//! decoding path as a real meter.
+
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::DmmError;
+use crate::parser::{DisplayIcon, Measurement};
+use crate::replay::{Capture, ReplayTransport};
+use crate::transport::{NotificationStream, Transport};
+use crate::units::{parse_duration, parse_quantity};
+
+const DMM_ID: u8 = 3;
+const DISPLAY_COUNTS: f64 = 6000.0;
+const OVERLOAD_TEXT: &str = " .0L ";
+
+#[derive(Debug)]
+struct Range {
+    icons: &'static [DisplayIcon],
+    /// Value of one displayed unit in the base unit of the function.
+    scale: f64,
+    decimals: usize,
+}
+
+#[derive(Debug)]
+struct Function {
+    name: &'static str,
+    icons: &'static [DisplayIcon],
+    auto_range: bool,
+    ranges: &'static [Range],
+}
+
+macro_rules! range {
+    ($scale:expr, $decimals:expr, $($icon:ident),*) => {
+        Range {
+            icons: &[$(DisplayIcon::$icon),*],
+            scale: $scale,
+            decimals: $decimals,
+        }
+    };
+}
+
+static VOLTAGE_RANGES: [Range; 4] = [
+    range!(1e-3, 1, Volt, MilliVolt),
+    range!(1.0, 3, Volt),
+    range!(1.0, 2, Volt),
+    range!(1.0, 1, Volt),
+];
+
+static CURRENT_RANGES: [Range; 6] = [
+    range!(1e-6, 1, MicroAmpere),
+    range!(1e-3, 3, MilliAmpere),
+    range!(1e-3, 2, MilliAmpere),
+    range!(1e-3, 1, MilliAmpere),
+    range!(1.0, 3, Ampere),
+    range!(1.0, 2, Ampere),
+];
+
+static FUNCTIONS: [Function; 11] = [
+    Function {
+        name: "dc-volts",
+        icons: &[DisplayIcon::DC],
+        auto_range: true,
+        ranges: &VOLTAGE_RANGES,
+    },
+    Function {
+        name: "ac-volts",
+        icons: &[DisplayIcon::AC],
+        auto_range: true,
+        ranges: &VOLTAGE_RANGES,
+    },
+    Function {
+        name: "dc-amps",
+        icons: &[DisplayIcon::DC],
+        auto_range: true,
+        ranges: &CURRENT_RANGES,
+    },
+    Function {
+        name: "ac-amps",
+        icons: &[DisplayIcon::AC],
+        auto_range: true,
+        ranges: &CURRENT_RANGES,
+    },
+    Function {
+        name: "ohms",
+        icons: &[],
+        auto_range: true,
+        ranges: &[
+            range!(1.0, 1, Ohm),
+            range!(1e3, 3, KiloOhm),
+            range!(1e3, 2, KiloOhm),
+            range!(1e3, 1, KiloOhm),
+            range!(1e6, 3, MegaOhm),
+            range!(1e6, 2, MegaOhm),
+        ],
+    },
+    Function {
+        name: "continuity",
+        icons: &[DisplayIcon::Buzz],
+        auto_range: false,
+        ranges: &[range!(1.0, 1, Ohm)],
+    },
+    Function {
+        name: "diode",
+        icons: &[DisplayIcon::Diode],
+        auto_range: false,
+        ranges: &[range!(1.0, 3, Volt)],
+    },
+    Function {
+        name: "capacitance",
+        icons: &[],
+        auto_range: true,
+        ranges: &[
+            range!(1e-9, 3, Farad, NanoFarad),
+            range!(1e-9, 2, Farad, NanoFarad),
+            range!(1e-9, 1, Farad, NanoFarad),
+            range!(1e-6, 3, Farad, MicroFarad),
+            range!(1e-6, 2, Farad, MicroFarad),
+            range!(1e-6, 1, Farad, MicroFarad),
+            range!(1e-3, 3, Farad, MilliFarad),
+        ],
+    },
+    Function {
+        name: "frequency",
+        icons: &[],
+        auto_range: true,
+        ranges: &[
+            range!(1.0, 3, Hertz),
+            range!(1.0, 2, Hertz),
+            range!(1.0, 1, Hertz),
+            range!(1.0, 0, Hertz),
+        ],
+    },
+    Function {
+        name: "temperature",
+        icons: &[],
+        auto_range: false,
+        ranges: &[range!(1.0, 0, DegC)],
+    },
+    Function {
+        name: "temperature-f",
+        icons: &[],
+        auto_range: false,
+        ranges: &[range!(1.0, 0, DegF)],
+    },
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Signal {
+    Constant(f64),
+    Ramp(f64, f64),
+    Overload,
+}
+
+#[derive(Debug)]
+enum Step {
+    Measure {
+        function: &'static Function,
+        signal: Signal,
+        duration: Duration,
+        noise: f64,
+        auto_range: bool,
+        flags: Vec<DisplayIcon>,
+    },
+    Wait(Duration),
+    Disconnect,
+}
+
+#[derive(Debug)]
+pub struct Scenario {
+    steps: Vec<Step>,
+}
+
+impl Scenario {
+    pub fn parse(text: &str) -> Result<Scenario, DmmError> {
+        let mut steps = Vec::new();
+
+        for (lineno, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let step =
+                parse_step(&line.split_whitespace().collect::<Vec<_>>()).map_err(|what| {
+                    DmmError::InvalidScenario(format!("line {}: {}", lineno + 1, what))
+                })?;
+            steps.push(step);
+        }
+
+        Ok(Scenario { steps })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Scenario, Box<dyn Error>> {
+        Ok(Scenario::parse(&fs::read_to_string(path)?)?)
+    }
+
+    /// Generates the frames of the scenario, one capture per connection.
+    pub fn render(&self, interval: Duration, seed: u64) -> Result<Vec<Capture>, DmmError> {
+        let mut rng = XorShift(seed.max(1));
+        let mut sessions = vec![Capture::new()];
+        let mut time = Duration::ZERO;
+
+        for step in &self.steps {
+            match step {
+                Step::Measure {
+                    function,
+                    signal,
+                    duration,
+                    noise,
+                    auto_range,
+                    flags,
+                } => {
+                    let frames = (duration.as_secs_f64() / interval.as_secs_f64()).ceil() as u32;
+                    for i in 0..frames.max(1) {
+                        // Ramps end on their last value
+                        let progress = if frames > 1 {
+                            i as f64 / (frames - 1) as f64
+                        } else {
+                            0.0
+                        };
+                        let value = match signal {
+                            Signal::Constant(value) => Some(*value),
+                            Signal::Ramp(from, to) => Some(from + (to - from) * progress),
+                            Signal::Overload => None,
+                        }
+                        .map(|v| v + noise * rng.next_signed());
+
+                        let frame = render_frame(function, value, *auto_range, flags)?;
+                        sessions.last_mut().unwrap().push(time, frame.to_vec());
+                        time += interval;
+                    }
+                }
+                Step::Wait(duration) => time += *duration,
+                Step::Disconnect => {
+                    sessions.push(Capture::new());
+                    time = Duration::ZERO;
+                }
+            }
+        }
+
+        Ok(sessions)
+    }
+}
+
+fn parse_step(tokens: &[&str]) -> Result<Step, String> {
+    let duration = |token: Option<&&str>| {
+        token
+            .and_then(|t| parse_duration(t))
+            .ok_or_else(|| "expected a duration".to_string())
+    };
+    let quantity = |token: Option<&&str>| {
+        token
+            .and_then(|t| parse_quantity(t))
+            .ok_or_else(|| "expected a value".to_string())
+    };
+
+    let (name, rest) = tokens.split_first().ok_or("empty step")?;
+    match *name {
+        "disconnect" if rest.is_empty() => return Ok(Step::Disconnect),
+        "wait" if rest.len() == 1 => return Ok(Step::Wait(duration(rest.first())?)),
+        "disconnect" | "wait" => return Err(format!("unexpected arguments to {}", name)),
+        _ => {}
+    }
+
+    let function = FUNCTIONS
+        .iter()
+        .find(|f| f.name == *name)
+        .ok_or_else(|| format!("unknown function {:?}", name))?;
+
+    let (signal, rest) = match rest.split_first() {
+        Some((&"const", rest)) => (Signal::Constant(quantity(rest.first())?), &rest[1..]),
+        Some((&"ramp", rest)) if rest.len() >= 2 => (
+            Signal::Ramp(quantity(rest.first())?, quantity(rest.get(1))?),
+            &rest[2..],
+        ),
+        Some((&"overload", rest)) => (Signal::Overload, rest),
+        _ => return Err("expected const, ramp or overload".to_string()),
+    };
+
+    let rest = match rest.split_first() {
+        Some((&"for", rest)) if !rest.is_empty() => rest,
+        _ => return Err("expected \"for <duration>\"".to_string()),
+    };
+    let duration = duration(rest.first())?;
+
+    let mut noise = 0.0;
+    let mut auto_range = function.auto_range;
+    let mut flags = Vec::new();
+    let mut options = rest[1..].iter();
+    while let Some(option) = options.next() {
+        match *option {
+            "noise" => noise = quantity(options.next())?,
+            "hold" => flags.push(DisplayIcon::Hold),
+            "relative" => flags.push(DisplayIcon::Delta),
+            "low-battery" => flags.push(DisplayIcon::LowBattery),
+            "manual" => auto_range = false,
+            other => return Err(format!("unknown option {:?}", other)),
+        }
+    }
+
+    Ok(Step::Measure {
+        function,
+        signal,
+        duration,
+        noise,
+        auto_range,
+        flags,
+    })
+}
+
+/// Encodes the frame shown by `function` for `value`, or overload if `None`.
+fn render_frame(
+    function: &Function,
+    value: Option<f64>,
+    auto_range: bool,
+    flags: &[DisplayIcon],
+) -> Result<[u8; 11], DmmError> {
+    let fits = |range: &Range, value: f64| {
+        (value.abs() / range.scale * 10f64.powi(range.decimals as i32)).round() < DISPLAY_COUNTS
+    };
+    let ranges = if auto_range {
+        function.ranges
+    } else {
+        &function.ranges[function.ranges.len() - 1..]
+    };
+    let range = value
+        .and_then(|v| ranges.iter().find(|r| fits(r, v)))
+        .unwrap_or(&ranges[ranges.len() - 1]);
+
+    let text = match value {
+        Some(value) if fits(range, value) => {
+            let shown = value / range.scale;
+            let width = if range.decimals > 0 { 5 } else { 4 };
+            let text = format!("{:0w$.d$}", shown.abs(), w = width, d = range.decimals);
+            if shown < 0.0 && text.chars().any(|c| c.is_ascii_digit() && c != '0') {
+                format!("-{}", text)
+            } else {
+                text
+            }
+        }
+        _ => OVERLOAD_TEXT.to_string(),
+    };
+
+    let mut icons = vec![DisplayIcon::Bluetooth];
+    icons.extend_from_slice(function.icons);
+    icons.extend_from_slice(range.icons);
+    icons.extend_from_slice(flags);
+    if auto_range {
+        icons.push(DisplayIcon::Auto);
+    }
+
+    Measurement::encode(DMM_ID, &text, &icons)
+}
+
+/// Small deterministic generator for the noise, so scenarios are repeatable.
+struct XorShift(u64);
+
+impl XorShift {
+    /// Returns a number in `[-1, 1)`.
+    fn next_signed(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 11) as f64 / (1u64 << 52) as f64 - 1.0
+    }
+}
+
+#[derive(Debug, Default)]
+struct SimulatorState {
+    /// Sessions not played yet, rendered on the first connection.
+    sessions: Option<VecDeque<Capture>>,
+    replay: Option<ReplayTransport>,
+}
+
+/// Transport that plays a `Scenario` as if it came from a connected meter.
+#[derive(Debug, Clone)]
+pub struct SimulatorTransport {
+    scenario: Arc<Scenario>,
+    interval: Duration,
+    seed: u64,
+    speed: f64,
+    state: Arc<Mutex<SimulatorState>>,
+}
+
+impl SimulatorTransport {
+    pub fn new(scenario: Scenario) -> SimulatorTransport {
+        SimulatorTransport {
+            scenario: Arc::new(scenario),
+            interval: Duration::from_millis(500),
+            seed: 1,
+            speed: 1.0,
+            state: Arc::new(Mutex::new(SimulatorState::default())),
+        }
+    }
+
+    /// Sets the time between two frames, 500 ms by default.
+    pub fn interval(mut self, interval: Duration) -> Result<SimulatorTransport, DmmError> {
+        if interval.is_zero() {
+            return Err(DmmError::InvalidPlayback(
+                "the frame interval must not be zero".to_string(),
+            ));
+        }
+        self.interval = interval;
+        Ok(self)
+    }
+
+    /// Sets the seed of the noise generator.
+    pub fn seed(mut self, seed: u64) -> SimulatorTransport {
+        self.seed = seed;
+        self
+    }
+
+    /// Sets the playback speed, e.g. `10.0` runs the scenario ten times faster.
+    pub fn speed(mut self, speed: f64) -> Result<SimulatorTransport, DmmError> {
+        if !(speed > 0.0 && speed.is_finite()) {
+            return Err(DmmError::InvalidPlayback(format!(
+                "the speed must be positive, got {}",
+                speed
+            )));
+        }
+        self.speed = speed;
+        Ok(self)
+    }
+
+    fn replay(&self) -> Option<ReplayTransport> {
+        self.state.lock().unwrap().replay.clone()
+    }
+}
+
+#[async_trait]
+impl Transport for SimulatorTransport {
+    async fn connect(&self) -> Result<(), Box<dyn Error>> {
+        let replay = {
+            let mut state = self.state.lock().unwrap();
+            if state.replay.is_some() {
+                return Err(DmmError::AlreadyConnected.into());
+            }
+            if state.sessions.is_none() {
+                let sessions = self.scenario.render(self.interval, self.seed)?;
+                state.sessions = Some(sessions.into());
+            }
+            let session = state
+                .sessions
+                .as_mut()
+                .unwrap()
+                .pop_front()
+                .ok_or(DmmError::DeviceNotFound)?;
+            let replay = ReplayTransport::new(session).speed(self.speed)?;
+            state.replay = Some(replay.clone());
+            replay
+        };
+        replay.connect().await
+    }
+
+    async fn disconnect(&self) -> Result<(), Box<dyn Error>> {
+        let replay = self.state.lock().unwrap().replay.take();
+        match replay {
+            Some(replay) => replay.disconnect().await,
+            None => Err(DmmError::NotConnected.into()),
+        }
+    }
+
+    async fn notifications(&self) -> Result<NotificationStream, Box<dyn Error>> {
+        match self.replay() {
+            Some(replay) => replay.notifications().await,
+            None => Err(DmmError::NotConnected.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DisplayValue, DmmDevice};
+
+    #[test]
+    fn test_parse_scenario() {
+        let scenario = Scenario::parse(
+            "
+            # comment
+            dc-volts ramp 0 12 for 30s noise 50m
+            dc-volts overload for 2s
+            ohms const 4.7k for 500ms hold manual
+            wait 1min
+            disconnect
+            ",
+        )
+        .unwrap();
+        assert_eq!(scenario.steps.len(), 5);
+        match &scenario.steps[0] {
+            Step::Measure {
+                function,
+                signal,
+                duration,
+                noise,
+                ..
+            } => {
+                assert_eq!(function.name, "dc-volts");
+                assert_eq!(*signal, Signal::Ramp(0.0, 12.0));
+                assert_eq!(*duration, Duration::from_secs(30));
+                assert!((noise - 0.05).abs() < 1e-9);
+            }
+            step => panic!("unexpected step {:?}", step),
+        }
+        match &scenario.steps[2] {
+            Step::Measure {
+                auto_range, flags, ..
+            } => {
+                assert!(!auto_range);
+                assert_eq!(flags, &vec![DisplayIcon::Hold]);
+            }
+            step => panic!("unexpected step {:?}", step),
+        }
+
+        assert!(Scenario::parse("volts const 1 for 1s").is_err());
+        assert!(Scenario::parse("ohms const 1 for").is_err());
+        assert!(Scenario::parse("ohms const 1x for 1s").is_err());
+        assert!(Scenario::parse("ohms const 1 for 1s sparkles").is_err());
+        assert!(Scenario::parse("disconnect now").is_err());
+    }
+
+    #[test]
+    fn test_render_frame() {
+        let function = FUNCTIONS.iter().find(|f| f.name == "dc-volts").unwrap();
+        let decode = |value, auto_range| {
+            let frame = render_frame(function, value, auto_range, &[]).unwrap();
+            Measurement::from_bytes(&frame).unwrap()
+        };
+
+        let measurement = decode(Some(0.4321), true);
+        assert_eq!(measurement.displayed_value, DisplayValue::Number(432.1));
+        assert_eq!(measurement.value_unit, Some("mV".to_string()));
+
+        let measurement = decode(Some(-9.57), true);
+        assert_eq!(measurement.displayed_value, DisplayValue::Number(-9.57));
+        assert_eq!(measurement.value_unit, Some("V".to_string()));
+        assert!(measurement.displayed_icons.contains(&DisplayIcon::Auto));
+
+        let measurement = decode(Some(1.5), false);
+        assert_eq!(measurement.displayed_value, DisplayValue::Number(1.5));
+        assert!(!measurement.displayed_icons.contains(&DisplayIcon::Auto));
+
+        let measurement = decode(Some(1000.0), true);
+        assert_eq!(
+            measurement.displayed_value,
+            DisplayValue::Text(OVERLOAD_TEXT.to_string())
+        );
+        assert_eq!(
+            decode(None, true).displayed_value,
+            measurement.displayed_value
+        );
+    }
+
+    #[test]
+    fn test_simulator() {
+        let scenario = Scenario::parse(
+            "
+            dc-volts ramp 1 10 for 2s
+            disconnect
+            ohms const 1.5k for 1s
+            ",
+        )
+        .unwrap();
+        let transport = SimulatorTransport::new(scenario)
+            .interval(Duration::from_millis(500))
+            .unwrap()
+            .speed(100.0)
+            .unwrap();
+        let dmm = DmmDevice::new(transport);
+        let timeout = Duration::from_secs(1);
+
+        async_std::task::block_on(async {
+            dmm.connect().await.unwrap();
+            for value in [1.0, 4.0, 7.0, 10.0] {
+                let measurement = dmm.next_event(timeout).await.unwrap();
+                assert_eq!(measurement.displayed_value, DisplayValue::Number(value));
+                assert_eq!(measurement.value_unit, Some("V".to_string()));
+            }
+            assert!(dmm.next_event(timeout).await.is_err());
+
+            dmm.device().disconnect().await.unwrap();
+            dmm.connect().await.unwrap();
+            let measurement = dmm.next_event(timeout).await.unwrap();
+            assert_eq!(measurement.displayed_value, DisplayValue::Number(1.5));
+            assert_eq!(measurement.value_unit, Some("kΩ".to_string()));
+
+            dmm.device().disconnect().await.unwrap();
+            assert!(dmm.connect().await.is_err());
+        });
+
+        let transport =
+            || SimulatorTransport::new(Scenario::parse("ohms const 1k for 1s").unwrap());
+        assert!(transport().speed(0.0).is_err());
+        assert!(transport().speed(f64::NAN).is_err());
+        assert!(transport().interval(Duration::ZERO).is_err());
+    }
+}
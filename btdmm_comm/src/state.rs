@@ -0,0 +1,361 @@
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+
+use crate::calibration::MeterRange;
+use crate::parser::{DisplayIcon, Mode};
+use crate::timed::TimedMeasurement;
+
+/// Resistance below which the meters beep in continuity mode.
+const CONTINUITY_THRESHOLD: f64 = 50.0;
+
+/// Change in the state of the meter, as shown by its display.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MeterEventKind {
+    /// `None` stands for a display without a known mode.
+    ModeChanged {
+        from: Option<Mode>,
+        to: Option<Mode>,
+    },
+    /// The meter switched to another range of the same mode.
+    RangeChanged {
+        from: MeterRange,
+        to: MeterRange,
+    },
+    HoldEngaged,
+    HoldReleased,
+    RelativeEngaged,
+    RelativeReleased,
+    OverloadStarted,
+    OverloadEnded,
+    /// The low battery icon lit up.
+    LowBattery,
+    /// The resistance fell below the continuity threshold.
+    ContinuityBeep,
+    AutoRangeToggled {
+        auto: bool,
+    },
+}
+
+impl fmt::Display for MeterEventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = |mode: &Option<Mode>| mode.map_or("unknown", |mode| mode.name());
+        match self {
+            MeterEventKind::ModeChanged { from, to } => {
+                write!(f, "mode changed from {} to {}", name(from), name(to))
+            }
+            MeterEventKind::RangeChanged { from, to } => write!(
+                f,
+                "range changed from {} ({} decimals) to {} ({} decimals)",
+                from.unit, from.decimals, to.unit, to.decimals
+            ),
+            MeterEventKind::HoldEngaged => f.write_str("hold engaged"),
+            MeterEventKind::HoldReleased => f.write_str("hold released"),
+            MeterEventKind::RelativeEngaged => f.write_str("relative engaged"),
+            MeterEventKind::RelativeReleased => f.write_str("relative released"),
+            MeterEventKind::OverloadStarted => f.write_str("overload started"),
+            MeterEventKind::OverloadEnded => f.write_str("overload ended"),
+            MeterEventKind::LowBattery => f.write_str("low battery"),
+            MeterEventKind::ContinuityBeep => f.write_str("continuity beep"),
+            MeterEventKind::AutoRangeToggled { auto: true } => f.write_str("auto range on"),
+            MeterEventKind::AutoRangeToggled { auto: false } => f.write_str("auto range off"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeterEvent {
+    pub kind: MeterEventKind,
+    /// When the reading showing the change was received.
+    pub timestamp: DateTime<Utc>,
+    /// Sequence number of the reading showing the change.
+    pub sequence: u64,
+}
+
+/// Follows the state of the meter through its readings, turning the icons
+/// and the display into events such as mode changes or hold presses.
+///
+/// The state starts unknown with everything off, so the first reading gives
+/// the mode and the functions already engaged.
+#[derive(Debug, Clone)]
+pub struct MeterState {
+    continuity_threshold: f64,
+    started: bool,
+    mode: Option<Mode>,
+    range: Option<MeterRange>,
+    hold: bool,
+    relative: bool,
+    overload: bool,
+    low_battery: bool,
+    beeping: bool,
+    auto_range: bool,
+}
+
+impl Default for MeterState {
+    fn default() -> MeterState {
+        MeterState {
+            continuity_threshold: CONTINUITY_THRESHOLD,
+            started: false,
+            mode: None,
+            range: None,
+            hold: false,
+            relative: false,
+            overload: false,
+            low_battery: false,
+            beeping: false,
+            auto_range: false,
+        }
+    }
+}
+
+impl MeterState {
+    pub fn new() -> MeterState {
+        MeterState::default()
+    }
+
+    /// Sets the resistance, in ohms, below which the meter beeps in
+    /// continuity mode (50 Ω by default).
+    pub fn continuity_threshold(mut self, ohms: f64) -> Self {
+        self.continuity_threshold = ohms;
+        self
+    }
+
+    /// Feeds the next reading, returning the changes it shows, if any.
+    pub fn push(&mut self, reading: &TimedMeasurement) -> Vec<MeterEvent> {
+        let measurement = &reading.measurement;
+        let mut kinds = Vec::new();
+
+        let mode = measurement.mode();
+        // Overloads and other text don't tell the range reliably
+        let range = MeterRange::of(measurement).filter(|_| measurement.value().is_some());
+        if !self.started || mode != self.mode {
+            kinds.push(MeterEventKind::ModeChanged {
+                from: self.mode,
+                to: mode,
+            });
+        } else if let (Some(from), Some(to)) = (&self.range, &range) {
+            if from != to {
+                kinds.push(MeterEventKind::RangeChanged {
+                    from: from.clone(),
+                    to: to.clone(),
+                });
+            }
+        }
+        if range.is_some() || mode != self.mode {
+            self.range = range;
+        }
+        self.started = true;
+        self.mode = mode;
+
+        let toggle = |state: &mut bool, on: bool, engaged, released| {
+            let changed = *state != on;
+            *state = on;
+            match (changed, on) {
+                (true, true) => engaged,
+                (true, false) => released,
+                (false, _) => None,
+            }
+        };
+        kinds.extend(toggle(
+            &mut self.hold,
+            measurement.has_icon(&DisplayIcon::Hold),
+            Some(MeterEventKind::HoldEngaged),
+            Some(MeterEventKind::HoldReleased),
+        ));
+        kinds.extend(toggle(
+            &mut self.relative,
+            measurement.has_icon(&DisplayIcon::Delta),
+            Some(MeterEventKind::RelativeEngaged),
+            Some(MeterEventKind::RelativeReleased),
+        ));
+        kinds.extend(toggle(
+            &mut self.overload,
+            measurement.is_overload(),
+            Some(MeterEventKind::OverloadStarted),
+            Some(MeterEventKind::OverloadEnded),
+        ));
+        kinds.extend(toggle(
+            &mut self.low_battery,
+            measurement.has_icon(&DisplayIcon::LowBattery),
+            Some(MeterEventKind::LowBattery),
+            None,
+        ));
+        let beeping = mode == Some(Mode::Continuity)
+            && measurement
+                .base_value()
+                .is_some_and(|ohms| ohms < self.continuity_threshold);
+        kinds.extend(toggle(
+            &mut self.beeping,
+            beeping,
+            Some(MeterEventKind::ContinuityBeep),
+            None,
+        ));
+        let auto_range = measurement.has_icon(&DisplayIcon::Auto);
+        kinds.extend(toggle(
+            &mut self.auto_range,
+            auto_range,
+            Some(MeterEventKind::AutoRangeToggled { auto: true }),
+            Some(MeterEventKind::AutoRangeToggled { auto: false }),
+        ));
+
+        kinds
+            .into_iter()
+            .map(|kind| MeterEvent {
+                kind,
+                timestamp: reading.timestamp,
+                sequence: reading.sequence,
+            })
+            .collect()
+    }
+
+    pub fn mode(&self) -> Option<Mode> {
+        self.mode
+    }
+
+    /// Range of the last reading of the mode showing a number.
+    pub fn range(&self) -> Option<&MeterRange> {
+        self.range.as_ref()
+    }
+
+    pub fn hold(&self) -> bool {
+        self.hold
+    }
+
+    pub fn relative(&self) -> bool {
+        self.relative
+    }
+
+    pub fn overload(&self) -> bool {
+        self.overload
+    }
+
+    pub fn low_battery(&self) -> bool {
+        self.low_battery
+    }
+
+    pub fn auto_range(&self) -> bool {
+        self.auto_range
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timed::reading;
+
+    /// Reading numbered `sequence` since the meter was connected.
+    fn numbered(text: &str, icons: &[DisplayIcon], sequence: u64) -> TimedMeasurement {
+        TimedMeasurement {
+            sequence,
+            ..reading(text, icons)
+        }
+    }
+
+    fn kinds(state: &mut MeterState, reading: &TimedMeasurement) -> Vec<MeterEventKind> {
+        let events = state.push(reading);
+        assert!(events.iter().all(|e| e.sequence == reading.sequence));
+        events.into_iter().map(|e| e.kind).collect()
+    }
+
+    #[test]
+    fn test_meter_state() {
+        use DisplayIcon::{Auto, Buzz, DC, Delta, Hold, KiloOhm, Ohm, Volt};
+        use MeterEventKind::*;
+
+        let mut state = MeterState::new();
+        assert_eq!(
+            kinds(&mut state, &numbered("1.234", &[Volt, DC, Auto], 0)),
+            [
+                ModeChanged {
+                    from: None,
+                    to: Some(Mode::DcVoltage)
+                },
+                AutoRangeToggled { auto: true },
+            ]
+        );
+        assert!(kinds(&mut state, &numbered("1.235", &[Volt, DC, Auto], 1)).is_empty());
+        assert_eq!(
+            kinds(&mut state, &numbered("12.35", &[Volt, DC, Hold], 2)),
+            [
+                RangeChanged {
+                    from: MeterRange {
+                        unit: "V".to_string(),
+                        decimals: 3
+                    },
+                    to: MeterRange {
+                        unit: "V".to_string(),
+                        decimals: 2
+                    },
+                },
+                HoldEngaged,
+                AutoRangeToggled { auto: false },
+            ]
+        );
+        assert_eq!(
+            kinds(
+                &mut state,
+                &numbered(" .0L ", &[Volt, DC, Delta, DisplayIcon::LowBattery], 3)
+            ),
+            [HoldReleased, RelativeEngaged, OverloadStarted, LowBattery]
+        );
+        assert!(state.relative() && state.overload() && state.low_battery());
+        assert_eq!(
+            kinds(
+                &mut state,
+                &numbered("12.35", &[Volt, DC, DisplayIcon::LowBattery], 4)
+            ),
+            [RelativeReleased, OverloadEnded]
+        );
+
+        assert_eq!(
+            kinds(
+                &mut state,
+                &numbered("0.500", &[KiloOhm, Buzz, DisplayIcon::LowBattery], 5)
+            ),
+            [ModeChanged {
+                from: Some(Mode::DcVoltage),
+                to: Some(Mode::Continuity)
+            }]
+        );
+        assert_eq!(
+            kinds(
+                &mut state,
+                &numbered("012.3", &[Ohm, Buzz, DisplayIcon::LowBattery], 6)
+            ),
+            [
+                RangeChanged {
+                    from: MeterRange {
+                        unit: "kΩ".to_string(),
+                        decimals: 3
+                    },
+                    to: MeterRange {
+                        unit: "Ω".to_string(),
+                        decimals: 1
+                    },
+                },
+                ContinuityBeep,
+            ]
+        );
+        assert!(
+            kinds(
+                &mut state,
+                &numbered("000.2", &[Ohm, Buzz, DisplayIcon::LowBattery], 7)
+            )
+            .is_empty()
+        );
+        assert_eq!(state.range().unwrap().unit, "Ω");
+    }
+
+    #[test]
+    fn test_event_display() {
+        let kind = MeterEventKind::ModeChanged {
+            from: Some(Mode::DcVoltage),
+            to: None,
+        };
+        assert_eq!(kind.to_string(), "mode changed from dc-volts to unknown");
+        assert_eq!(
+            MeterEventKind::AutoRangeToggled { auto: false }.to_string(),
+            "auto range off"
+        );
+    }
+}
@@ -0,0 +1,195 @@
+use chrono::{DateTime, Utc};
+
+use crate::parser::Mode;
+use crate::timed::TimedMeasurement;
+
+/// Statistics over a run of readings taken in the same mode and unit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statistics {
+    pub mode: Option<Mode>,
+    pub unit: Option<String>,
+    pub started: DateTime<Utc>,
+    /// Numeric readings, overloads and other text are not counted.
+    pub count: u64,
+    /// When the reading last changed value.
+    pub last_change: Option<DateTime<Utc>>,
+    last: Option<f64>,
+    min: f64,
+    max: f64,
+    // Welford's running mean and sum of squared differences
+    mean: f64,
+    m2: f64,
+}
+
+impl Statistics {
+    fn new(mode: Option<Mode>, unit: Option<String>, started: DateTime<Utc>) -> Statistics {
+        Statistics {
+            mode,
+            unit,
+            started,
+            count: 0,
+            last_change: None,
+            last: None,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            mean: 0.0,
+            m2: 0.0,
+        }
+    }
+
+    fn push(&mut self, value: f64, timestamp: DateTime<Utc>) {
+        if self.last != Some(value) {
+            self.last_change = Some(timestamp);
+        }
+        self.last = Some(value);
+        self.count += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    pub fn last(&self) -> Option<f64> {
+        self.last
+    }
+
+    pub fn min(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.min)
+    }
+
+    pub fn max(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.max)
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.mean)
+    }
+
+    /// Sample standard deviation.
+    pub fn std_dev(&self) -> Option<f64> {
+        match self.count {
+            0 => None,
+            1 => Some(0.0),
+            n => Some((self.m2 / (n - 1) as f64).sqrt()),
+        }
+    }
+
+    pub fn peak_to_peak(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.max - self.min)
+    }
+}
+
+/// Keeps running statistics over a measurement stream, starting a new segment
+/// whenever the mode or the unit changes.
+#[derive(Debug, Default)]
+pub struct StatsTracker {
+    current: Option<Statistics>,
+    finished: Vec<Statistics>,
+}
+
+impl StatsTracker {
+    pub fn new() -> StatsTracker {
+        StatsTracker::default()
+    }
+
+    pub fn push(&mut self, timed: &TimedMeasurement) {
+        let measurement = &timed.measurement;
+        let mode = measurement.mode();
+        let unit = &measurement.value_unit;
+
+        let same_segment = self
+            .current
+            .as_ref()
+            .is_some_and(|s| s.mode == mode && &s.unit == unit);
+        if !same_segment {
+            let segment = Statistics::new(mode, unit.clone(), timed.timestamp);
+            if let Some(previous) = self.current.replace(segment) {
+                if previous.count > 0 {
+                    self.finished.push(previous);
+                }
+            }
+        }
+
+        if let Some(value) = measurement.value() {
+            self.current.as_mut().unwrap().push(value, timed.timestamp);
+        }
+    }
+
+    /// Statistics of the segment in progress.
+    pub fn current(&self) -> Option<&Statistics> {
+        self.current.as_ref()
+    }
+
+    /// All the segments with at least one reading, oldest first.
+    pub fn segments(&self) -> impl Iterator<Item = &Statistics> {
+        self.finished
+            .iter()
+            .chain(self.current.iter().filter(|s| s.count > 0))
+    }
+
+    /// Segments taken in the given mode, oldest first.
+    pub fn by_mode(&self, mode: Mode) -> impl Iterator<Item = &Statistics> {
+        self.segments().filter(move |s| s.mode == Some(mode))
+    }
+
+    /// Forgets everything; the next reading starts a new segment.
+    pub fn reset(&mut self) {
+        self.current = None;
+        self.finished.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::DisplayIcon;
+    use crate::timed::reading;
+    use chrono::TimeDelta;
+
+    #[test]
+    fn test_stats() {
+        let volts = |text, seconds| TimedMeasurement {
+            timestamp: DateTime::UNIX_EPOCH + TimeDelta::seconds(seconds),
+            ..reading(text, &[DisplayIcon::Volt, DisplayIcon::DC])
+        };
+        let mut tracker = StatsTracker::new();
+
+        tracker.push(&volts("1.000", 0));
+        tracker.push(&volts("2.000", 1));
+        tracker.push(&volts(" .0L ", 2));
+        let last_change = tracker.current().unwrap().last_change;
+        tracker.push(&volts("2.000", 3));
+        tracker.push(&volts("4.000", 4));
+
+        let stats = tracker.current().unwrap();
+        assert_eq!(stats.mode, Some(Mode::DcVoltage));
+        assert_eq!(stats.unit, Some("V".to_string()));
+        assert_eq!(stats.count, 4);
+        assert_eq!(stats.min(), Some(1.0));
+        assert_eq!(stats.max(), Some(4.0));
+        assert_eq!(stats.mean(), Some(2.25));
+        assert_eq!(stats.peak_to_peak(), Some(3.0));
+        assert_eq!(stats.last(), Some(4.0));
+        assert!((stats.std_dev().unwrap() - 1.258305739).abs() < 1e-9);
+        assert!(stats.last_change > last_change);
+
+        // Switching range, then mode, starts new segments
+        tracker.push(&reading(
+            "500.0",
+            &[DisplayIcon::Volt, DisplayIcon::MilliVolt],
+        ));
+        tracker.push(&reading("Auto", &[]));
+        tracker.push(&reading("4.700", &[DisplayIcon::KiloOhm]));
+        assert_eq!(tracker.segments().count(), 3);
+        assert_eq!(tracker.by_mode(Mode::DcVoltage).count(), 2);
+        let stats = tracker.current().unwrap();
+        assert_eq!(stats.mode, Some(Mode::Resistance));
+        assert_eq!(stats.count, 1);
+        assert_eq!(stats.std_dev(), Some(0.0));
+
+        tracker.reset();
+        assert!(tracker.current().is_none());
+        assert_eq!(tracker.segments().count(), 0);
+    }
+}
@@ -0,0 +1,59 @@
+use std::error::Error;
+use std::time::Instant;
+
+use chrono::{DateTime, Utc};
+
+use crate::parser::Measurement;
+
+/// Raw frame as notified by the meter, stamped when it was received.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub data: Vec<u8>,
+    pub received: Instant,
+    pub timestamp: DateTime<Utc>,
+    /// Position of the frame since the device was connected, starting at 0.
+    pub sequence: u64,
+}
+
+impl Frame {
+    pub(crate) fn new(data: Vec<u8>, sequence: u64) -> Frame {
+        Frame {
+            data,
+            received: Instant::now(),
+            timestamp: Utc::now(),
+            sequence,
+        }
+    }
+}
+
+/// Measurement together with the time its frame was received.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimedMeasurement {
+    pub measurement: Measurement,
+    /// Monotonic reception time, for computing intervals.
+    pub received: Instant,
+    /// Wall-clock reception time, for logging.
+    pub timestamp: DateTime<Utc>,
+    pub sequence: u64,
+    /// The measurement as decoded, when `measurement` holds a calibrated value.
+    pub raw: Option<Measurement>,
+}
+
+impl TimedMeasurement {
+    pub fn decode(frame: &Frame) -> Result<TimedMeasurement, Box<dyn Error>> {
+        Ok(TimedMeasurement {
+            measurement: Measurement::from_bytes(&frame.data)?,
+            received: frame.received,
+            timestamp: frame.timestamp,
+            sequence: frame.sequence,
+            raw: None,
+        })
+    }
+}
+
+/// Decodes a reading of a type 3 meter showing `text` and `icons`, received now.
+#[cfg(test)]
+pub(crate) fn reading(text: &str, icons: &[crate::parser::DisplayIcon]) -> TimedMeasurement {
+    let data = Measurement::encode(3, text, icons).unwrap().to_vec();
+    TimedMeasurement::decode(&Frame::new(data, 0)).unwrap()
+}
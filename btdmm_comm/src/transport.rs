@@ -0,0 +1,87 @@
+use std::error::Error;
+use std::pin::Pin;
+
+use async_trait::async_trait;
+use btleplug::api::{CharPropFlags, Peripheral, ValueNotification, WriteType};
+use futures::Stream;
+use uuid::Uuid;
+
+use crate::DmmError;
+use crate::device::DMM_CHARACTERISTIC_UUID;
+
+pub type NotificationStream = Pin<Box<dyn Stream<Item = ValueNotification> + Send>>;
+
+/// Link used by `DmmDevice` to talk to a meter.
+///
+/// Every btleplug `Peripheral` is a transport; other implementations (such as
+/// `ReplayTransport`) feed frames from somewhere other than the radio. Clones
+/// must refer to the same link, so a device can be disconnected in the background.
+#[async_trait]
+pub trait Transport: Clone + Send + Sync + 'static {
+    /// Connects to the meter and subscribes to its measurement characteristic.
+    async fn connect(&self) -> Result<(), Box<dyn Error>>;
+
+    async fn disconnect(&self) -> Result<(), Box<dyn Error>>;
+
+    /// Returns the notifications received from now on.
+    async fn notifications(&self) -> Result<NotificationStream, Box<dyn Error>>;
+
+    /// Writes to a characteristic of the meter. Transports that can't talk back
+    /// to a meter return `DmmError::Unsupported`.
+    async fn write(&self, _characteristic: Uuid, _data: &[u8]) -> Result<(), Box<dyn Error>> {
+        Err(DmmError::Unsupported.into())
+    }
+
+    /// Returns the current signal strength in dBm, if the transport knows it.
+    async fn rssi(&self) -> Result<Option<i16>, Box<dyn Error>> {
+        Ok(None)
+    }
+}
+
+#[async_trait]
+impl<P: Peripheral + 'static> Transport for P {
+    async fn connect(&self) -> Result<(), Box<dyn Error>> {
+        Peripheral::connect(self).await?;
+        self.discover_services().await?;
+
+        let chars = self.characteristics();
+        let char = chars
+            .iter()
+            .find(|c| c.uuid == DMM_CHARACTERISTIC_UUID)
+            .ok_or(DmmError::CharacteristicNotFound)?;
+        self.subscribe(char).await?;
+
+        Ok(())
+    }
+
+    async fn disconnect(&self) -> Result<(), Box<dyn Error>> {
+        Peripheral::disconnect(self).await?;
+        Ok(())
+    }
+
+    async fn notifications(&self) -> Result<NotificationStream, Box<dyn Error>> {
+        Ok(Peripheral::notifications(self).await?)
+    }
+
+    async fn write(&self, characteristic: Uuid, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        let chars = self.characteristics();
+        let char = chars
+            .iter()
+            .find(|c| c.uuid == characteristic)
+            .ok_or(DmmError::Unsupported)?;
+        let write_type = if char
+            .properties
+            .contains(CharPropFlags::WRITE_WITHOUT_RESPONSE)
+        {
+            WriteType::WithoutResponse
+        } else {
+            WriteType::WithResponse
+        };
+        Peripheral::write(self, char, data, write_type).await?;
+        Ok(())
+    }
+
+    async fn rssi(&self) -> Result<Option<i16>, Box<dyn Error>> {
+        Ok(self.properties().await?.and_then(|p| p.rssi))
+    }
+}
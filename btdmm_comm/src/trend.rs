@@ -0,0 +1,194 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::parser::Mode;
+use crate::timed::TimedMeasurement;
+use crate::units::format_si;
+
+/// Fewest readings a trend is fitted on.
+const MIN_READINGS: usize = 3;
+
+/// Straight line fitted on the recent readings of a mode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trend {
+    pub mode: Mode,
+    /// Value of the line at the last reading, in the base unit of the mode:
+    /// the reading with the noise smoothed out.
+    pub value: f64,
+    /// Rate of change, in the base unit of the mode per second.
+    pub slope: f64,
+    /// Readings the line was fitted on.
+    pub readings: usize,
+    /// Time between the first and the last of these readings.
+    pub span: Duration,
+}
+
+impl Trend {
+    pub fn per_minute(&self) -> f64 {
+        self.slope * 60.0
+    }
+
+    /// Estimates when the reading reaches `target`, in the base unit of the
+    /// mode, from the last reading. `None` if the trend heads away from it.
+    pub fn time_to(&self, target: f64) -> Option<Duration> {
+        if target == self.value {
+            return Some(Duration::ZERO);
+        }
+        Duration::try_from_secs_f64((target - self.value) / self.slope).ok()
+    }
+}
+
+impl fmt::Display for Trend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unit = self.mode.unit();
+        let sign = if self.slope > 0.0 { "+" } else { "" };
+        write!(
+            f,
+            "{}, {}{}/s ({}{}/min)",
+            format_si(self.value, unit),
+            sign,
+            format_si(self.slope, unit),
+            sign,
+            format_si(self.per_minute(), unit)
+        )
+    }
+}
+
+/// Fits a line by least squares on the readings of a moving time window, for
+/// the rate of change of slow processes such as charging or warming up.
+///
+/// Overloads and readings without a number are skipped; a mode change starts
+/// the window over.
+#[derive(Debug, Clone)]
+pub struct TrendTracker {
+    window: Duration,
+    mode: Option<Mode>,
+    /// Monotonic reception time and base value of the readings, the wall
+    /// clock being liable to jump.
+    readings: VecDeque<(Instant, f64)>,
+}
+
+impl TrendTracker {
+    pub fn new(window: Duration) -> TrendTracker {
+        TrendTracker {
+            window,
+            mode: None,
+            readings: VecDeque::new(),
+        }
+    }
+
+    /// Feeds the next reading, returning the trend once the window has
+    /// enough readings.
+    pub fn push(&mut self, reading: &TimedMeasurement) -> Option<Trend> {
+        let measurement = &reading.measurement;
+        let mode = measurement.mode()?;
+        let value = measurement.base_value()?;
+        if self.mode != Some(mode) {
+            self.mode = Some(mode);
+            self.readings.clear();
+        }
+
+        let now = reading.received;
+        self.readings.push_back((now, value));
+        while self
+            .readings
+            .front()
+            .is_some_and(|(t, _)| now.saturating_duration_since(*t) > self.window)
+        {
+            self.readings.pop_front();
+        }
+        self.trend(mode)
+    }
+
+    pub fn reset(&mut self) {
+        self.mode = None;
+        self.readings.clear();
+    }
+
+    fn trend(&self, mode: Mode) -> Option<Trend> {
+        if self.readings.len() < MIN_READINGS {
+            return None;
+        }
+        let (first, _) = *self.readings.front()?;
+        let (last, _) = *self.readings.back()?;
+
+        // Time relative to the last reading, so the intercept is the value
+        // of the line at that reading
+        let points: Vec<(f64, f64)> = self
+            .readings
+            .iter()
+            .map(|(t, v)| (-last.saturating_duration_since(*t).as_secs_f64(), *v))
+            .collect();
+        let n = points.len() as f64;
+        let mean_t = points.iter().map(|(t, _)| t).sum::<f64>() / n;
+        let mean_v = points.iter().map(|(_, v)| v).sum::<f64>() / n;
+        let stt: f64 = points.iter().map(|(t, _)| (t - mean_t).powi(2)).sum();
+        let stv: f64 = points
+            .iter()
+            .map(|(t, v)| (t - mean_t) * (v - mean_v))
+            .sum();
+        if stt == 0.0 {
+            return None;
+        }
+        let slope = stv / stt;
+
+        Some(Trend {
+            mode,
+            value: mean_v - slope * mean_t,
+            slope,
+            readings: points.len(),
+            span: last.saturating_duration_since(first),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::DisplayIcon;
+    use crate::timed::reading;
+
+    #[test]
+    fn test_trend() {
+        let start = Instant::now();
+        let dc = |text, icon, seconds| TimedMeasurement {
+            received: start + Duration::from_secs(seconds),
+            ..reading(text, &[icon, DisplayIcon::DC])
+        };
+        let mut tracker = TrendTracker::new(Duration::from_secs(10));
+        let volts = |text, seconds| dc(text, DisplayIcon::Volt, seconds);
+
+        assert!(tracker.push(&volts("3.600", 0)).is_none());
+        assert!(tracker.push(&volts(" .0L ", 1)).is_none());
+        assert!(tracker.push(&volts("3.620", 2)).is_none());
+        let trend = tracker.push(&volts("3.640", 4)).unwrap();
+        assert!((trend.slope - 0.01).abs() < 1e-9);
+        assert!((trend.value - 3.64).abs() < 1e-9);
+        assert_eq!(trend.readings, 3);
+        assert_eq!(trend.span, Duration::from_secs(4));
+        assert_eq!(trend.to_string(), "3.64 V, +10 mV/s (+600 mV/min)");
+
+        let time = trend.time_to(4.2).unwrap();
+        assert!((time.as_secs_f64() - 56.0).abs() < 1e-6);
+        assert_eq!(trend.time_to(3.0), None);
+
+        // Noise is smoothed out
+        tracker.push(&volts("3.670", 6));
+        let trend = tracker.push(&volts("3.670", 8)).unwrap();
+        assert!((trend.slope - 0.0095).abs() < 1e-9);
+        assert!((trend.value - 3.678).abs() < 1e-9);
+
+        // The window moves on
+        let trend = tracker.push(&volts("3.700", 12)).unwrap();
+        assert_eq!(trend.readings, 5);
+        assert_eq!(trend.span, Duration::from_secs(10));
+
+        // A mode change starts over
+        assert!(
+            tracker
+                .push(&dc("1.000", DisplayIcon::Ampere, 13))
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,360 @@
+//! Scope-like captures of the readings around a trigger condition.
+//!
+//! A trigger is written as `<mode> rising <value>`, `<mode> falling <value>`,
+//! `mode-change` or `overload`. Modes are named as in scenarios (`dc-volts`,
+//! `ohms`...) and values are in the base unit of the mode, with the `n`, `u`,
+//! `m`, `k` and `M` suffixes.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::DmmError;
+use crate::parser::Mode;
+use crate::replay::Capture;
+use crate::state::{MeterEventKind, MeterState};
+use crate::timed::TimedMeasurement;
+use crate::units::{format_si, parse_quantity};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Trigger {
+    /// The reading goes from below the threshold to above it.
+    Rising { mode: Mode, threshold: f64 },
+    /// The reading goes from above the threshold to below it.
+    Falling { mode: Mode, threshold: f64 },
+    /// The meter switches to another mode.
+    ModeChange,
+    /// The meter starts showing "0L".
+    Overload,
+}
+
+impl FromStr for Trigger {
+    type Err = DmmError;
+
+    /// Parses the trigger format described in the module documentation.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |what: &str| DmmError::InvalidTrigger(format!("{:?}: {}", s, what));
+        match s.split_whitespace().collect::<Vec<_>>()[..] {
+            ["mode-change"] => Ok(Trigger::ModeChange),
+            ["overload"] => Ok(Trigger::Overload),
+            [mode, edge @ ("rising" | "falling"), value] => {
+                let mode = mode.parse()?;
+                let threshold = parse_quantity(value).ok_or_else(|| invalid("invalid value"))?;
+                Ok(match edge {
+                    "rising" => Trigger::Rising { mode, threshold },
+                    _ => Trigger::Falling { mode, threshold },
+                })
+            }
+            _ => Err(invalid(
+                "expected \"<mode> rising|falling <value>\", mode-change or overload",
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Trigger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Trigger::Rising { mode, threshold } => {
+                write!(f, "{} rising {}", mode, format_si(*threshold, mode.unit()))
+            }
+            Trigger::Falling { mode, threshold } => {
+                write!(f, "{} falling {}", mode, format_si(*threshold, mode.unit()))
+            }
+            Trigger::ModeChange => f.write_str("mode-change"),
+            Trigger::Overload => f.write_str("overload"),
+        }
+    }
+}
+
+/// Readings around a trigger, as saved by a `TriggerBuffer`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TriggeredCapture {
+    pub trigger: Trigger,
+    /// Readings in the order they were received.
+    pub readings: Vec<TimedMeasurement>,
+    /// Index of the reading that fired the trigger.
+    pub trigger_index: usize,
+}
+
+impl TriggeredCapture {
+    pub fn trigger_reading(&self) -> &TimedMeasurement {
+        &self.readings[self.trigger_index]
+    }
+
+    /// Returns the frames of the readings as a capture that can be replayed,
+    /// timed from the first reading. Calibrated readings are saved as they
+    /// were received.
+    pub fn to_capture(&self) -> Result<Capture, DmmError> {
+        let mut capture = Capture::new();
+        let Some(start) = self.readings.first().map(|r| r.received) else {
+            return Ok(capture);
+        };
+        for reading in &self.readings {
+            let measurement = reading.raw.as_ref().unwrap_or(&reading.measurement);
+            capture.push(
+                reading.received.saturating_duration_since(start),
+                measurement.to_bytes()?.to_vec(),
+            );
+        }
+        Ok(capture)
+    }
+}
+
+/// What a reading fed to a `TriggerBuffer` did.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TriggerUpdate {
+    /// Whether the reading fired the trigger, starting a capture.
+    pub fired: bool,
+    /// Capture completed by the reading, if any.
+    pub capture: Option<TriggeredCapture>,
+}
+
+/// Keeps the readings of the last `pre` seconds in a ring buffer and, once
+/// the trigger fires, the readings of the next `post` seconds, returning them
+/// all as one capture. The trigger is armed again after each capture.
+#[derive(Debug, Clone)]
+pub struct TriggerBuffer {
+    trigger: Trigger,
+    pre: Duration,
+    post: Duration,
+    buffer: VecDeque<TimedMeasurement>,
+    state: MeterState,
+    /// Last reading with a number, for the threshold crossings.
+    last: Option<(Mode, f64)>,
+    /// When the trigger fired and the index of its reading in the buffer.
+    fired: Option<(Instant, usize)>,
+}
+
+impl TriggerBuffer {
+    /// Keeps 5 seconds of readings before and after the trigger.
+    pub fn new(trigger: Trigger) -> TriggerBuffer {
+        TriggerBuffer {
+            trigger,
+            pre: Duration::from_secs(5),
+            post: Duration::from_secs(5),
+            buffer: VecDeque::new(),
+            state: MeterState::new(),
+            last: None,
+            fired: None,
+        }
+    }
+
+    pub fn pre_trigger(mut self, duration: Duration) -> Self {
+        self.pre = duration;
+        self
+    }
+
+    pub fn post_trigger(mut self, duration: Duration) -> Self {
+        self.post = duration;
+        self
+    }
+
+    pub fn trigger(&self) -> Trigger {
+        self.trigger
+    }
+
+    /// Tells whether the trigger fired and the buffer is waiting for the
+    /// readings after it.
+    pub fn is_triggered(&self) -> bool {
+        self.fired.is_some()
+    }
+
+    /// Feeds the next reading, telling whether it fired the trigger and
+    /// returning the capture it completes, if any. A reading can do both.
+    pub fn push(&mut self, reading: &TimedMeasurement) -> TriggerUpdate {
+        let fires = self.fires(reading);
+
+        let mut capture = None;
+        if let Some((fired, _)) = self.fired {
+            if reading.received.saturating_duration_since(fired) <= self.post {
+                self.buffer.push_back(reading.clone());
+                return TriggerUpdate::default();
+            }
+            capture = self.flush();
+        }
+
+        self.start(reading, fires);
+        TriggerUpdate {
+            fired: fires,
+            capture,
+        }
+    }
+
+    /// Returns the capture in progress, with the readings after the trigger
+    /// received so far, and arms the trigger again.
+    pub fn flush(&mut self) -> Option<TriggeredCapture> {
+        let (_, trigger_index) = self.fired.take()?;
+        Some(TriggeredCapture {
+            trigger: self.trigger,
+            readings: self.buffer.drain(..).collect(),
+            trigger_index,
+        })
+    }
+
+    /// Adds a reading while waiting for the trigger, dropping the readings
+    /// older than the pre-trigger time.
+    fn start(&mut self, reading: &TimedMeasurement, fires: bool) {
+        while self.buffer.front().is_some_and(|oldest| {
+            reading.received.saturating_duration_since(oldest.received) > self.pre
+        }) {
+            self.buffer.pop_front();
+        }
+        self.buffer.push_back(reading.clone());
+        if fires {
+            self.fired = Some((reading.received, self.buffer.len() - 1));
+        }
+    }
+
+    fn fires(&mut self, reading: &TimedMeasurement) -> bool {
+        let events = self.state.push(reading);
+        let measurement = &reading.measurement;
+        let current = measurement.mode().zip(measurement.base_value());
+        let previous = self.last;
+        if current.is_some() {
+            self.last = current;
+        }
+        // Value before and after, when both readings are in the same mode
+        let crossing = match (previous, current) {
+            (Some((m0, before)), Some((m1, after))) if m0 == m1 => Some((m1, before, after)),
+            _ => None,
+        };
+
+        match self.trigger {
+            Trigger::Rising { mode, threshold } => crossing.is_some_and(|(m, before, after)| {
+                m == mode && before <= threshold && after > threshold
+            }),
+            Trigger::Falling { mode, threshold } => crossing.is_some_and(|(m, before, after)| {
+                m == mode && before >= threshold && after < threshold
+            }),
+            // The first reading only tells the initial mode
+            Trigger::ModeChange => events.iter().any(|event| {
+                matches!(
+                    event.kind,
+                    MeterEventKind::ModeChanged { from: Some(_), .. }
+                )
+            }),
+            Trigger::Overload => events
+                .iter()
+                .any(|event| event.kind == MeterEventKind::OverloadStarted),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::DisplayIcon;
+    use crate::timed::reading;
+
+    /// Reading received `ms` milliseconds after `start`, numbered `ms`.
+    fn dc(text: &str, icon: DisplayIcon, start: Instant, ms: u64) -> TimedMeasurement {
+        TimedMeasurement {
+            received: start + Duration::from_millis(ms),
+            sequence: ms,
+            ..reading(text, &[icon, DisplayIcon::DC])
+        }
+    }
+
+    #[test]
+    fn test_parse_trigger() {
+        assert_eq!(
+            "dc-volts rising 3.3".parse::<Trigger>().unwrap(),
+            Trigger::Rising {
+                mode: Mode::DcVoltage,
+                threshold: 3.3
+            }
+        );
+        let falling: Trigger = "ohms falling 4.7k".parse().unwrap();
+        assert_eq!(falling.to_string(), "ohms falling 4.7 kΩ");
+        assert_eq!("overload".parse::<Trigger>().unwrap(), Trigger::Overload);
+        assert!("dc-volts rising".parse::<Trigger>().is_err());
+        assert!("dc-volts above 3".parse::<Trigger>().is_err());
+        assert!("watts rising 3".parse::<Trigger>().is_err());
+    }
+
+    #[test]
+    fn test_pre_post_trigger() {
+        let start = Instant::now();
+        let volts = |text, ms| dc(text, DisplayIcon::Volt, start, ms);
+        let mut buffer = TriggerBuffer::new("dc-volts rising 3".parse().unwrap())
+            .pre_trigger(Duration::from_secs(1))
+            .post_trigger(Duration::from_secs(1));
+
+        let readings = [
+            volts("3.500", 0),
+            volts("2.000", 500),
+            volts("2.500", 1000),
+            volts("2.900", 1500),
+            volts("3.100", 2000),
+            volts("2.000", 2500),
+            volts("3.200", 3000),
+            volts("3.300", 3500),
+        ];
+        // Crossings during the post-trigger time don't fire again
+        let captures: Vec<_> = readings
+            .iter()
+            .filter_map(|r| buffer.push(r).capture)
+            .collect();
+        assert!(!buffer.is_triggered());
+
+        let sequences = |capture: &TriggeredCapture| {
+            capture
+                .readings
+                .iter()
+                .map(|r| r.sequence)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(captures.len(), 1);
+        assert_eq!(sequences(&captures[0]), [1000, 1500, 2000, 2500, 3000]);
+        assert_eq!(captures[0].trigger_reading().sequence, 2000);
+
+        let capture = captures[0].to_capture().unwrap();
+        assert_eq!(capture.duration(), Duration::from_secs(2));
+        assert_eq!(
+            capture.frames()[2].data,
+            readings[4].measurement.to_bytes().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_event_triggers() {
+        let start = Instant::now();
+        let mut overload = TriggerBuffer::new(Trigger::Overload).post_trigger(Duration::ZERO);
+        let mut mode_change = TriggerBuffer::new(Trigger::ModeChange).post_trigger(Duration::ZERO);
+
+        let readings = [
+            dc("1.000", DisplayIcon::Volt, start, 0),
+            dc(" .0L ", DisplayIcon::Volt, start, 500),
+            dc("1.000", DisplayIcon::Ampere, start, 1000),
+            dc("1.000", DisplayIcon::Ampere, start, 1500),
+        ];
+        let fired = |buffer: &mut TriggerBuffer| {
+            readings
+                .iter()
+                .filter_map(|r| buffer.push(r).capture)
+                .map(|c| c.trigger_reading().sequence)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(fired(&mut overload), [500]);
+        assert_eq!(fired(&mut mode_change), [1000]);
+    }
+
+    #[test]
+    fn test_refire() {
+        let start = Instant::now();
+        let volts = |text, ms| dc(text, DisplayIcon::Volt, start, ms);
+        let mut buffer = TriggerBuffer::new("dc-volts rising 3".parse().unwrap())
+            .post_trigger(Duration::from_secs(1));
+
+        assert_eq!(buffer.push(&volts("2.000", 0)), TriggerUpdate::default());
+        assert!(buffer.push(&volts("3.500", 500)).fired);
+        assert_eq!(buffer.push(&volts("2.000", 1000)), TriggerUpdate::default());
+        // The reading ending the capture fires the trigger again
+        let update = buffer.push(&volts("3.500", 2000));
+        assert!(update.fired);
+        assert_eq!(update.capture.unwrap().readings.len(), 3);
+        assert!(buffer.is_triggered());
+    }
+}
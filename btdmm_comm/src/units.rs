@@ -0,0 +1,327 @@
+//! Quantities written with SI prefixes, as in the scenario files, and
+//! conversions between the units shown by the meters.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::DmmError;
+use crate::parser::{Measurement, Mode};
+
+/// SI prefixes and their powers of ten.
+static PREFIXES: [(&str, i32); 8] = [
+    ("p", -12),
+    ("n", -9),
+    ("μ", -6),
+    ("µ", -6),
+    ("u", -6),
+    ("m", -3),
+    ("k", 3),
+    ("M", 6),
+];
+
+static BASE_UNITS: [&str; 9] = ["V", "A", "Ω", "F", "Hz", "%", "°C", "°F", "K"];
+
+/// Significant digits kept by conversions, dropping the floating point noise.
+const SIGNIFICANT_DIGITS: i32 = 9;
+
+/// Parses a duration in `ms`, `s` or `min`, e.g. `1.5s`.
+pub(crate) fn parse_duration(text: &str) -> Option<Duration> {
+    let (number, scale) = if let Some(n) = text.strip_suffix("ms") {
+        (n, 1e-3)
+    } else if let Some(n) = text.strip_suffix("min") {
+        (n, 60.0)
+    } else if let Some(n) = text.strip_suffix('s') {
+        (n, 1.0)
+    } else {
+        return None;
+    };
+    Duration::try_from_secs_f64(number.parse::<f64>().ok()? * scale).ok()
+}
+
+/// Parses a number with an optional `n`, `u`, `m`, `k` or `M` suffix, e.g.
+/// `4.7k`.
+pub(crate) fn parse_quantity(text: &str) -> Option<f64> {
+    let (number, scale) = match text.char_indices().last()? {
+        (i, 'n') => (&text[..i], 1e-9),
+        (i, 'u') | (i, 'µ') | (i, 'μ') => (&text[..i], 1e-6),
+        (i, 'm') => (&text[..i], 1e-3),
+        (i, 'k') => (&text[..i], 1e3),
+        (i, 'M') => (&text[..i], 1e6),
+        _ => (text, 1.0),
+    };
+    number.parse::<f64>().ok().map(|n| n * scale)
+}
+
+/// Formats a value in a base unit with an SI prefix, e.g. `4.7 kΩ`.
+pub(crate) fn format_si(value: f64, unit: &str) -> String {
+    let prefixes = [
+        (1e6, "M"),
+        (1e3, "k"),
+        (1.0, ""),
+        (1e-3, "m"),
+        (1e-6, "μ"),
+        (1e-9, "n"),
+        (1e-12, "p"),
+    ];
+    let (factor, prefix) = prefixes
+        .into_iter()
+        .find(|(factor, _)| value.abs() >= *factor * 0.999_999)
+        .unwrap_or(if value == 0.0 {
+            (1.0, "")
+        } else {
+            (1e-12, "p")
+        });
+    format!("{} {}{}", round_significant(value / factor), prefix, unit)
+}
+
+/// Rounds to 4 significant digits, the resolution of the meters.
+fn round_significant(value: f64) -> f64 {
+    if value == 0.0 || !value.is_finite() {
+        return value;
+    }
+    let exponent = value.abs().log10().floor() as i32 - 3;
+    scale(scale(value, -exponent).round(), exponent)
+}
+
+/// Multiplies `value` by 10^`exponent`, dividing for negative exponents so
+/// that values like 4.7 come out exact.
+pub(crate) fn scale(value: f64, exponent: i32) -> f64 {
+    if exponent >= 0 {
+        value * 10f64.powi(exponent)
+    } else {
+        value / 10f64.powi(-exponent)
+    }
+}
+
+fn round(value: f64) -> f64 {
+    if value == 0.0 || !value.is_finite() {
+        return value;
+    }
+    let exponent = value.abs().log10().floor() as i32 - (SIGNIFICANT_DIGITS - 1);
+    scale(scale(value, -exponent).round(), exponent)
+}
+
+/// Splits a unit into the power of ten of its prefix and its base unit, e.g.
+/// `mA` into -3 and `A`.
+fn split_prefix(unit: &str) -> Option<(i32, &str)> {
+    if BASE_UNITS.contains(&unit) {
+        return Some((0, unit));
+    }
+    PREFIXES.iter().find_map(|(prefix, exponent)| {
+        let base = unit.strip_prefix(prefix)?;
+        BASE_UNITS.contains(&base).then_some((*exponent, base))
+    })
+}
+
+/// Unit of a temperature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TemperatureUnit {
+    Celsius,
+    Fahrenheit,
+    Kelvin,
+}
+
+impl TemperatureUnit {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            TemperatureUnit::Celsius => "°C",
+            TemperatureUnit::Fahrenheit => "°F",
+            TemperatureUnit::Kelvin => "K",
+        }
+    }
+
+    /// Converts a temperature in this unit to `unit`.
+    pub fn convert(&self, value: f64, unit: TemperatureUnit) -> f64 {
+        let celsius = match self {
+            TemperatureUnit::Celsius => value,
+            TemperatureUnit::Fahrenheit => (value - 32.0) * 5.0 / 9.0,
+            TemperatureUnit::Kelvin => value - 273.15,
+        };
+        round(match unit {
+            TemperatureUnit::Celsius => celsius,
+            TemperatureUnit::Fahrenheit => celsius * 9.0 / 5.0 + 32.0,
+            TemperatureUnit::Kelvin => celsius + 273.15,
+        })
+    }
+}
+
+impl FromStr for TemperatureUnit {
+    type Err = DmmError;
+
+    /// Parses `°C`, `C`, `°F`, `F` or `K`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim_start_matches('°') {
+            "C" => Ok(TemperatureUnit::Celsius),
+            "F" => Ok(TemperatureUnit::Fahrenheit),
+            "K" => Ok(TemperatureUnit::Kelvin),
+            _ => Err(DmmError::UnknownUnit(s.to_string())),
+        }
+    }
+}
+
+/// Value with its unit, such as `12.34 mA`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quantity {
+    pub value: f64,
+    pub unit: String,
+}
+
+impl Quantity {
+    pub fn new(value: f64, unit: &str) -> Quantity {
+        Quantity {
+            value,
+            unit: unit.to_string(),
+        }
+    }
+
+    /// Converts to another unit of the same quantity, rescaling prefixes
+    /// (`mA` to `A`) and converting temperatures (`°C` to `°F` or `K`).
+    pub fn convert(&self, unit: &str) -> Result<Quantity, DmmError> {
+        if let (Ok(from), Ok(to)) = (
+            self.unit.parse::<TemperatureUnit>(),
+            unit.parse::<TemperatureUnit>(),
+        ) {
+            return Ok(Quantity::new(from.convert(self.value, to), to.symbol()));
+        }
+
+        let incompatible =
+            || DmmError::UnknownUnit(format!("cannot convert {} to {}", self.unit, unit));
+        let (from, from_base) = split_prefix(&self.unit).ok_or_else(incompatible)?;
+        let (to, to_base) = split_prefix(unit).ok_or_else(incompatible)?;
+        if from_base != to_base {
+            return Err(incompatible());
+        }
+        Ok(Quantity::new(round(scale(self.value, from - to)), unit))
+    }
+}
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.value, self.unit)
+    }
+}
+
+/// Preferred unit of each mode, so a series of readings keeps the same unit
+/// when the meter changes ranges or settings.
+#[derive(Debug, Clone, Default)]
+pub struct UnitPreferences {
+    units: HashMap<Mode, String>,
+}
+
+impl UnitPreferences {
+    pub fn new() -> UnitPreferences {
+        UnitPreferences::default()
+    }
+
+    /// Shows the readings of `mode` in `unit`, failing if the mode can't be
+    /// expressed in it.
+    pub fn prefer(mut self, mode: Mode, unit: &str) -> Result<Self, DmmError> {
+        Quantity::new(0.0, mode.unit()).convert(unit)?;
+        self.units.insert(mode, unit.to_string());
+        Ok(self)
+    }
+
+    /// Unit of the readings of `mode`: the preferred one, or else the base
+    /// unit of the mode.
+    pub fn unit(&self, mode: Mode) -> &str {
+        self.units.get(&mode).map_or(mode.unit(), String::as_str)
+    }
+
+    /// Returns the reading in the unit of its mode, if it has a number and a
+    /// known mode.
+    pub fn normalize(&self, measurement: &Measurement) -> Option<Quantity> {
+        let unit = self.unit(measurement.mode()?);
+        measurement.quantity()?.convert(unit).ok()
+    }
+}
+
+impl FromStr for UnitPreferences {
+    type Err = DmmError;
+
+    /// Parses a comma separated list of `<mode>=<unit>`, e.g.
+    /// `temperature=°F,dc-amps=mA`, the empty string giving no preferences.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .filter(|preference| !preference.trim().is_empty())
+            .try_fold(UnitPreferences::new(), |preferences, preference| {
+                let (mode, unit) = preference
+                    .split_once('=')
+                    .ok_or_else(|| DmmError::UnknownUnit(preference.to_string()))?;
+                preferences.prefer(mode.trim().parse()?, unit.trim())
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::DisplayIcon;
+
+    fn measurement(text: &str, icons: &[DisplayIcon]) -> Measurement {
+        Measurement::from_bytes(&Measurement::encode(3, text, icons).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_convert() {
+        let current = Quantity::new(12.34, "mA");
+        assert_eq!(current.convert("A").unwrap(), Quantity::new(0.01234, "A"));
+        assert_eq!(current.convert("μA").unwrap().value, 12340.0);
+        assert_eq!(current.convert("uA").unwrap().value, 12340.0);
+        assert!(current.convert("V").is_err());
+        assert!(current.convert("mX").is_err());
+        assert_eq!(
+            Quantity::new(4.7, "kΩ").convert("Ω").unwrap().to_string(),
+            "4700 Ω"
+        );
+
+        let celsius = Quantity::new(23.4, "°C");
+        assert_eq!(celsius.convert("°F").unwrap(), Quantity::new(74.12, "°F"));
+        assert_eq!(celsius.convert("K").unwrap().value, 296.55);
+        assert_eq!(
+            Quantity::new(-40.0, "°F").convert("C").unwrap(),
+            Quantity::new(-40.0, "°C")
+        );
+        assert!(celsius.convert("V").is_err());
+    }
+
+    #[test]
+    fn test_preferences() {
+        let preferences: UnitPreferences = "temperature=K, dc-amps=mA".parse().unwrap();
+        assert_eq!(preferences.unit(Mode::DcCurrent), "mA");
+        assert_eq!(preferences.unit(Mode::DcVoltage), "V");
+
+        let amps = measurement("1.234", &[DisplayIcon::Ampere, DisplayIcon::DC]);
+        assert_eq!(
+            preferences.normalize(&amps),
+            Some(Quantity::new(1234.0, "mA"))
+        );
+        let millivolts = measurement("512.0", &[DisplayIcon::MilliVolt, DisplayIcon::DC]);
+        assert_eq!(
+            preferences.normalize(&millivolts),
+            Some(Quantity::new(0.512, "V"))
+        );
+        let fahrenheit = measurement("0212", &[DisplayIcon::DegF]);
+        assert_eq!(
+            preferences.normalize(&fahrenheit),
+            Some(Quantity::new(373.15, "K"))
+        );
+        let overload = measurement(" .0L ", &[DisplayIcon::Volt]);
+        assert_eq!(preferences.normalize(&overload), None);
+
+        assert_eq!(
+            "".parse::<UnitPreferences>().unwrap().unit(Mode::DcCurrent),
+            "A"
+        );
+        assert!("dc-volts=A".parse::<UnitPreferences>().is_err());
+        assert!("dc-volts".parse::<UnitPreferences>().is_err());
+        assert!("watts=W".parse::<UnitPreferences>().is_err());
+    }
+
+    #[test]
+    fn test_format_si() {
+        assert_eq!(format_si(4700.0, "Ω"), "4.7 kΩ");
+        assert_eq!(format_si(1e-7, "F"), "100 nF");
+    }
+}
@@ -0,0 +1,422 @@
+//! Verification of a meter against reference standards.
+//!
+//! The reference points to check can be read from a text file with one point
+//! per line, checked in order; blank lines and lines starting with `#` are
+//! ignored:
+//!
+//! ```text
+//! accuracy 0.5% + 2d
+//! dc-volts 1
+//! dc-volts 10
+//! ohms 1k accuracy 0.8% + 3d
+//! ```
+//!
+//! A point is `<mode> <value>`, with the mode named as in scenarios
+//! (`dc-volts`, `ohms`...) and the value in the base unit of the mode, with
+//! the `n`, `u`, `m`, `k` and `M` suffixes. It may be followed by
+//! `accuracy <spec>`, the accuracy of the meter at that point as given by its
+//! manual, e.g. `0.5% + 2d`. An `accuracy <spec>` line sets the accuracy of
+//! the points after it that don't have their own; points without accuracy are
+//! checked against the one of the model of the meter, when it is known.
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+
+use crate::DmmError;
+use crate::accuracy::Accuracy;
+use crate::parser::Mode;
+use crate::profile::{ModelProfile, profile_for};
+use crate::settle::StableReading;
+use crate::units::{format_si, parse_quantity};
+
+/// How far from the reference a settled reading may be to be taken as the
+/// reading of the point, in percent plus digits, so the value settled before
+/// the reference was connected isn't captured.
+const CAPTURE_RANGE: Accuracy = Accuracy {
+    percent: 10.0,
+    digits: 100,
+};
+
+/// Value of a reference standard the meter is checked against.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReferencePoint {
+    pub mode: Mode,
+    /// In the base unit of the mode.
+    pub value: f64,
+    pub accuracy: Option<Accuracy>,
+}
+
+impl fmt::Display for ReferencePoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}",
+            self.mode,
+            format_si(self.value, self.mode.unit())
+        )
+    }
+}
+
+/// Outcome of checking the meter at one reference point.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PointResult {
+    pub point: ReferencePoint,
+    /// Mean of the settled readings, in the base unit of the mode.
+    pub measured: f64,
+    /// Weight of the last displayed digit, in the base unit of the mode.
+    pub resolution: f64,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl PointResult {
+    /// Measured minus reference value, in the base unit of the mode.
+    pub fn error(&self) -> f64 {
+        self.measured - self.point.value
+    }
+
+    /// Error relative to the reference value, unless it is zero.
+    pub fn error_percent(&self) -> Option<f64> {
+        (self.point.value != 0.0).then(|| self.error() / self.point.value.abs() * 100.0)
+    }
+
+    /// Error in counts of the last displayed digit.
+    pub fn error_digits(&self) -> f64 {
+        self.error() / self.resolution
+    }
+
+    /// Largest error allowed by the accuracy of the point, if it has one.
+    pub fn allowed(&self) -> Option<f64> {
+        let accuracy = self.point.accuracy?;
+        Some(accuracy.allowed(self.measured, self.resolution))
+    }
+
+    /// Whether the error is within the accuracy, if the point has one.
+    pub fn pass(&self) -> Option<bool> {
+        // Leave some room for the rounding of the display
+        let allowed = self.allowed()?;
+        Some(self.error().abs() <= allowed + self.resolution * 1e-6)
+    }
+}
+
+impl fmt::Display for PointResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unit = self.point.mode.unit();
+        write!(
+            f,
+            "{}: read {}, error ",
+            self.point,
+            format_si(self.measured, unit)
+        )?;
+        if let Some(percent) = self.error_percent() {
+            write!(f, "{:+.3}% ", percent)?;
+        }
+        write!(f, "({:+.1}d)", self.error_digits())?;
+        match (self.point.accuracy, self.allowed(), self.pass()) {
+            (Some(accuracy), Some(allowed), Some(pass)) => write!(
+                f,
+                ", allowed {} = {:.1}d: {}",
+                accuracy,
+                allowed / self.resolution,
+                if pass { "pass" } else { "FAIL" }
+            ),
+            _ => write!(f, ", no accuracy given"),
+        }
+    }
+}
+
+/// Steps through reference points, taking the settled reading of each.
+#[derive(Debug, Clone)]
+pub struct Verification {
+    points: Vec<ReferencePoint>,
+    results: Vec<Option<PointResult>>,
+    profile: Option<&'static ModelProfile>,
+}
+
+impl Verification {
+    pub fn new(points: Vec<ReferencePoint>) -> Verification {
+        Verification {
+            points,
+            results: Vec::new(),
+            profile: None,
+        }
+    }
+
+    /// Parses the reference point format described in the module
+    /// documentation.
+    pub fn parse(text: &str) -> Result<Verification, DmmError> {
+        let mut points = Vec::new();
+        let mut accuracy = None;
+
+        for (lineno, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid =
+                |what: String| DmmError::InvalidReference(format!("line {}: {}", lineno + 1, what));
+
+            if let Some(spec) = line.strip_prefix("accuracy ") {
+                accuracy = Some(spec.parse().map_err(|e: DmmError| invalid(e.to_string()))?);
+                continue;
+            }
+
+            let (point, spec) = match line.split_once(" accuracy ") {
+                Some((point, spec)) => (point, Some(spec)),
+                None => (line, None),
+            };
+            let [mode, value] = point.split_whitespace().collect::<Vec<_>>()[..] else {
+                return Err(invalid("expected \"<mode> <value>\"".to_string()));
+            };
+            points.push(ReferencePoint {
+                mode: mode.parse().map_err(|e: DmmError| invalid(e.to_string()))?,
+                value: parse_quantity(value)
+                    .ok_or_else(|| invalid(format!("invalid value {:?}", value)))?,
+                accuracy: match spec {
+                    Some(spec) => Some(spec.parse().map_err(|e: DmmError| invalid(e.to_string()))?),
+                    None => accuracy,
+                },
+            });
+        }
+
+        Ok(Verification::new(points))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Verification, Box<dyn Error>> {
+        Ok(Verification::parse(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn points(&self) -> &[ReferencePoint] {
+        &self.points
+    }
+
+    /// Takes the accuracy of the points without one from `profile`, as set
+    /// with `DmmDevice::with_profile`, rather than from the profile of the
+    /// model reported by the readings.
+    pub fn set_profile(&mut self, profile: &'static ModelProfile) {
+        self.profile = Some(profile);
+    }
+
+    /// The point waiting for its reading, `None` once all were checked.
+    pub fn current(&self) -> Option<&ReferencePoint> {
+        self.points.get(self.results.len())
+    }
+
+    /// Takes a settled reading as the one of the current point if it is in
+    /// the mode of the point and close to its value, then moves on to the
+    /// next point.
+    pub fn push(&mut self, stable: &StableReading) -> Option<&PointResult> {
+        let point = self.current()?;
+        let measurement = &stable.reading.measurement;
+        let (Some(resolution), Some(mode)) = (measurement.resolution(), measurement.mode()) else {
+            return None;
+        };
+        let measured = measurement.to_base(stable.mean);
+        // Temperatures are converted with an offset
+        let resolution = measurement.to_base(resolution) - measurement.to_base(0.0);
+        let margin = CAPTURE_RANGE.allowed(point.value, resolution);
+        if mode != point.mode || (measured - point.value).abs() > margin {
+            return None;
+        }
+
+        let mut point = point.clone();
+        let profile = self
+            .profile
+            .unwrap_or_else(|| profile_for(measurement.dmm_id));
+        point.accuracy = point.accuracy.or_else(|| measurement.accuracy(profile));
+        self.results.push(Some(PointResult {
+            point,
+            measured,
+            resolution,
+            timestamp: stable.reading.timestamp,
+        }));
+        self.results.last()?.as_ref()
+    }
+
+    /// Moves on to the next point without checking the current one.
+    pub fn skip(&mut self) {
+        if self.current().is_some() {
+            self.results.push(None);
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.current().is_none()
+    }
+
+    /// Reports the points checked so far, the others as not measured.
+    pub fn report(&self, device: &str) -> VerificationReport {
+        VerificationReport {
+            device: device.to_string(),
+            points: self
+                .points
+                .iter()
+                .enumerate()
+                .map(|(i, point)| match self.results.get(i) {
+                    Some(Some(result)) => Ok(result.clone()),
+                    _ => Err(point.clone()),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Outcome of the verification of a meter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerificationReport {
+    pub device: String,
+    /// Result of each point, or the point if it wasn't measured.
+    pub points: Vec<Result<PointResult, ReferencePoint>>,
+}
+
+impl VerificationReport {
+    /// Whether every point was measured within its accuracy. Points without
+    /// accuracy don't fail the verification.
+    pub fn passed(&self) -> bool {
+        self.points
+            .iter()
+            .all(|point| point.as_ref().is_ok_and(|r| r.pass() != Some(false)))
+    }
+}
+
+impl fmt::Display for VerificationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Verification of {}", self.device)?;
+        let started = self.points.iter().flatten().map(|r| r.timestamp).min();
+        if let Some(started) = started {
+            writeln!(f, "Started: {}", started.to_rfc3339())?;
+        }
+        for point in &self.points {
+            match point {
+                Ok(result) => writeln!(f, "  {}", result)?,
+                Err(point) => writeln!(f, "  {}: not measured", point)?,
+            }
+        }
+        let passed = self
+            .points
+            .iter()
+            .flatten()
+            .filter(|r| r.pass() != Some(false))
+            .count();
+        write!(
+            f,
+            "Result: {} ({} of {} points passed)",
+            if self.passed() { "PASS" } else { "FAIL" },
+            passed,
+            self.points.len()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::DisplayIcon;
+    use crate::profile::DMM_3_PROFILE;
+    use crate::timed::reading;
+
+    fn stable(text: &str, icons: &[DisplayIcon]) -> StableReading {
+        let reading = reading(text, icons);
+        StableReading {
+            mean: reading.measurement.value().unwrap(),
+            unit: reading.measurement.value_unit.clone(),
+            reading,
+            readings: 3,
+        }
+    }
+
+    const POINTS: &str = "
+        # Voltage standard, then a 1k resistor
+        accuracy 0.5% + 2d
+        dc-volts 1
+        dc-volts 10
+        ohms 1k accuracy 0.8%+3d
+        ohms 0
+    ";
+
+    #[test]
+    fn test_parse() {
+        let verification = Verification::parse(POINTS).unwrap();
+        let points = verification.points();
+        assert_eq!(points.len(), 4);
+        assert_eq!(points[1].value, 10.0);
+        assert_eq!(
+            points[1].accuracy,
+            Some(Accuracy {
+                percent: 0.5,
+                digits: 2
+            })
+        );
+        assert_eq!(points[2].accuracy.unwrap().digits, 3);
+        assert_eq!(points[2].to_string(), "ohms 1 kΩ");
+
+        assert!("3d".parse::<Accuracy>().is_ok());
+        assert!("0.5% 2d".parse::<Accuracy>().is_err());
+        let error = Verification::parse("dc-volts").unwrap_err();
+        assert!(error.to_string().contains("line 1"));
+        assert!(Verification::parse("dc-volts 1 accuracy 2").is_err());
+    }
+
+    #[test]
+    fn test_verification() {
+        let volts = [DisplayIcon::Volt, DisplayIcon::DC];
+        let mut verification = Verification::parse(POINTS).unwrap();
+
+        // Readings before the reference is connected are ignored
+        assert!(verification.push(&stable("0.000", &volts)).is_none());
+        let result = verification.push(&stable("1.008", &volts)).unwrap();
+        assert!((result.error_percent().unwrap() - 0.8).abs() < 1e-9);
+        assert!((result.error_digits() - 8.0).abs() < 1e-9);
+        // 0.5% of 1.008 V + 2 mV
+        assert!((result.allowed().unwrap() - 0.00704).abs() < 1e-9);
+        assert_eq!(result.pass(), Some(false));
+
+        let result = verification.push(&stable("10.03", &volts)).unwrap();
+        assert_eq!(result.pass(), Some(true));
+        assert_eq!(
+            result.to_string(),
+            "dc-volts 10 V: read 10.03 V, error +0.300% (+3.0d), allowed ±(0.5% + 2d) = 7.0d: pass"
+        );
+
+        // Wrong mode
+        assert!(verification.push(&stable("1.000", &volts)).is_none());
+        let result = verification
+            .push(&stable("1.002", &[DisplayIcon::KiloOhm]))
+            .unwrap();
+        assert_eq!(result.pass(), Some(true));
+        assert_eq!(verification.current().unwrap().value, 0.0);
+        verification.skip();
+        assert!(verification.is_done());
+
+        let report = verification.report("12:34:56:78:9A:BC");
+        assert!(!report.passed());
+        let text = report.to_string();
+        assert!(text.contains("ohms 0 Ω: not measured"));
+        assert!(text.ends_with("Result: FAIL (2 of 4 points passed)"));
+
+        // Points without accuracy use the one of the model
+        let mut verification = Verification::parse("dc-volts 1").unwrap();
+        let result = verification.push(&stable("1.004", &volts)).unwrap();
+        assert_eq!(result.point.accuracy, Some("0.5% + 3d".parse().unwrap()));
+        // or the one of the profile set for the device
+        static UNKNOWN_ACCURACY: ModelProfile = ModelProfile {
+            accuracy: &[],
+            ..DMM_3_PROFILE
+        };
+        let mut verification = Verification::parse("dc-volts 1").unwrap();
+        verification.set_profile(&UNKNOWN_ACCURACY);
+        let result = verification.push(&stable("1.004", &volts)).unwrap();
+        assert_eq!(result.point.accuracy, None);
+
+        // Temperatures shown in °F are checked in °C
+        let mut verification = Verification::parse("temperature 100").unwrap();
+        let result = verification
+            .push(&stable("0212", &[DisplayIcon::DegF]))
+            .unwrap();
+        assert_eq!(result.measured, 100.0);
+        assert!((result.resolution - 5.0 / 9.0).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,142 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use chrono::Local;
+use futures::StreamExt;
+
+use btdmm_comm::{BatteryTest, Cutoff, DmmDevice, DmmError, PowerMeter, Transport};
+
+use crate::{abort_on_ctrl_c, disconnect};
+
+#[derive(clap::Args)]
+pub struct BatteryArgs {
+    /// Stop when the voltage drops below VOLTS (or rises above it with --charge)
+    #[arg(long, value_name = "VOLTS")]
+    cutoff: Option<f64>,
+    /// Test a charging battery
+    #[arg(long)]
+    charge: bool,
+    /// Stop after SECONDS
+    #[arg(long, value_name = "SECONDS")]
+    duration: Option<u64>,
+    /// Smallest current change, in amperes, used to estimate the internal resistance
+    #[arg(long, default_value_t = 0.01, value_name = "AMPERES")]
+    load_step: f64,
+    /// Save the samples to FILE as CSV
+    #[arg(long, value_name = "FILE")]
+    log: Option<PathBuf>,
+    /// Use a simulated meter running the scenario FILE instead of Bluetooth
+    /// meters (give one for the voltage and one for the current)
+    #[arg(long, value_name = "FILE")]
+    pub scenario: Vec<PathBuf>,
+    /// Run the scenarios faster than real time
+    #[arg(long, default_value_t = 1.0)]
+    pub speed: f64,
+}
+
+impl BatteryArgs {
+    fn test(&self) -> BatteryTest {
+        let mut test = BatteryTest::new().load_step(self.load_step);
+        if let Some(volts) = self.cutoff {
+            test = test.cutoff(if self.charge {
+                Cutoff::Above(volts)
+            } else {
+                Cutoff::Below(volts)
+            });
+        }
+        if let Some(seconds) = self.duration {
+            test = test.max_duration(Duration::from_secs(seconds));
+        }
+        test
+    }
+}
+
+/// Runs a battery test with the meters, then prints its report and releases
+/// the meters.
+pub async fn run<P: Transport>(
+    mut meters: Vec<DmmDevice<P>>,
+    args: &BatteryArgs,
+) -> Result<(), Box<dyn Error>> {
+    if meters.len() != 2 {
+        return Err(format!(
+            "A battery test needs two meters, one on V and one on A, found {}",
+            meters.len()
+        )
+        .into());
+    }
+    for dmm in &meters {
+        dmm.connect().await?;
+    }
+    println!("Connected to {} meters", meters.len());
+    println!();
+
+    let mut test = args.test();
+    let result = record(&meters, &mut test).await;
+
+    println!();
+    println!("{}", test.report());
+    if let Some(path) = &args.log {
+        let mut file = BufWriter::new(File::create(path)?);
+        test.write_csv(&mut file)?;
+        file.flush()?;
+        println!("Samples saved to {}", path.display());
+    }
+    io::stdout().flush()?;
+
+    for dmm in &mut meters {
+        disconnect(dmm).await;
+    }
+
+    result
+}
+
+async fn record<P: Transport>(
+    meters: &[DmmDevice<P>],
+    test: &mut BatteryTest,
+) -> Result<(), Box<dyn Error>> {
+    let (streams, handles): (Vec<_>, Vec<_>) = meters
+        .iter()
+        .map(|dmm| dmm.measurements(Duration::from_secs(5)))
+        .unzip();
+    abort_on_ctrl_c(handles);
+
+    let mut readings = futures::stream::select_all(streams);
+    let mut meter = PowerMeter::new();
+
+    while let Some(reading) = readings.next().await {
+        let reading = match reading {
+            Ok(reading) => reading,
+            Err(err) if matches!(err.downcast_ref(), Some(DmmError::DeviceDisconnected)) => {
+                test.stop();
+                return Err(err);
+            }
+            Err(err) => {
+                eprintln!("{}", err);
+                continue;
+            }
+        };
+
+        let Some(sample) = meter.push(&reading) else {
+            continue;
+        };
+        println!(
+            "{}  {:.3} V  {:.1} mA  {:.3} W  {:.1} mAh  {:.3} Wh",
+            sample.timestamp.with_timezone(&Local).format("%H:%M:%S"),
+            sample.voltage,
+            sample.current * 1000.0,
+            sample.power,
+            sample.charge * 1000.0,
+            sample.energy
+        );
+        if let Some(reason) = test.push(sample) {
+            println!("Stopping: {}", reason);
+            return Ok(());
+        }
+    }
+
+    test.stop();
+    Ok(())
+}
@@ -0,0 +1,97 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use futures::StreamExt;
+
+use btdmm_comm::{DmmDevice, DmmError, HoldCapture, Transport};
+
+use crate::{abort_on_ctrl_c, disconnect};
+
+#[derive(clap::Args)]
+pub struct CaptureArgs {
+    /// File listing the labels of the test points, in order (the points are
+    /// numbered otherwise)
+    #[arg(long, value_name = "FILE")]
+    points: Option<PathBuf>,
+    /// Save the captured points to FILE as CSV
+    #[arg(long, value_name = "FILE")]
+    log: Option<PathBuf>,
+    /// Use a simulated meter running the scenario FILE instead of a Bluetooth meter
+    #[arg(long, value_name = "FILE")]
+    pub scenario: Option<PathBuf>,
+    /// Run the scenario faster than real time
+    #[arg(long, default_value_t = 1.0)]
+    pub speed: f64,
+}
+
+/// Records a point each time HOLD is pressed on the meter, until all the
+/// listed points are captured or Ctrl-C is pressed, then releases the meter.
+pub async fn run<P: Transport>(
+    mut dmm: DmmDevice<P>,
+    device: &str,
+    args: &CaptureArgs,
+) -> Result<(), Box<dyn Error>> {
+    let mut capture = match &args.points {
+        Some(path) => HoldCapture::load(path)?,
+        None => HoldCapture::new(),
+    };
+
+    dmm.connect().await?;
+    println!("Connected to {}", device);
+    println!();
+
+    let result = capture_points(&dmm, &mut capture).await;
+
+    println!();
+    println!("Captured {} points", capture.points().len());
+    if let Some(path) = &args.log {
+        let mut file = BufWriter::new(File::create(path)?);
+        capture.write_csv(&mut file)?;
+        file.flush()?;
+        println!("Points saved to {}", path.display());
+    }
+    io::stdout().flush()?;
+
+    disconnect(&mut dmm).await;
+
+    result
+}
+
+async fn capture_points<P: Transport>(
+    dmm: &DmmDevice<P>,
+    capture: &mut HoldCapture,
+) -> Result<(), Box<dyn Error>> {
+    let (mut measurements, cancel) = dmm.measurements(Duration::from_secs(5));
+    abort_on_ctrl_c(vec![cancel]);
+
+    while let Some(label) = capture.next_label() {
+        println!("Press HOLD to record point {}", label);
+        io::stdout().flush()?;
+
+        loop {
+            let reading = match measurements.next().await {
+                Some(Ok(reading)) => reading,
+                Some(Err(err))
+                    if matches!(err.downcast_ref(), Some(DmmError::DeviceDisconnected)) =>
+                {
+                    return Err(err);
+                }
+                Some(Err(err)) => {
+                    eprintln!("{}", err);
+                    continue;
+                }
+                // Interrupted
+                None => return Ok(()),
+            };
+            if let Some(point) = capture.push(&reading) {
+                println!("  {}", point);
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -1,28 +1,373 @@
 use std::error::Error;
-use std::time::Duration;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
-use btleplug::api::{Manager, Peripheral};
+use btleplug::api::Peripheral;
+use chrono::Local;
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use futures::StreamExt;
+use futures::stream::AbortHandle;
 
-use btdmm_comm::DisplayValue;
+use btdmm_comm::{
+    AdapterSelector, AlarmEngine, BluetoothAdapter, Calibrations, ComponentSorter, DisplayValue,
+    DmmDevice, DmmError, ESeries, GlitchFilter, LinkStats, Measurement, MeterState, ModelProfile,
+    ReadingFilter, RelativeMode, RelativeReading, Scenario, SettleDetector, SimulatorTransport,
+    StatsTracker, TimedMeasurement, Tolerance, Transport, Trend, TrendTracker, UnitPreferences,
+    WindowAggregator, WindowSummary,
+};
+
+mod battery;
+mod capture;
+mod trigger;
+mod verify;
+
+#[derive(Parser)]
+#[command(about = "Client for Bluetooth multimeters")]
+struct Args {
+    /// Bluetooth adapter to use, by index or name (see `adapters`)
+    #[arg(long, global = true, value_name = "INDEX|NAME")]
+    adapter: Option<AdapterSelector>,
+    /// Correct the readings with the calibration profiles in FILE
+    #[arg(long, global = true, value_name = "FILE")]
+    calibration: Option<PathBuf>,
+    #[command(flatten)]
+    output: OutputArgs,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+impl Args {
+    /// Parses the command line, rejecting the output options of the default
+    /// monitor when given before a subcommand, where they would be ignored.
+    fn parse_checked() -> Args {
+        let mut command = Args::command();
+        let matches = command.get_matches_mut();
+        if let Some((name, _)) = matches.subcommand() {
+            // Only the global options are shared with the subcommands
+            let misplaced = command.get_arguments().find(|arg| {
+                !arg.is_global_set()
+                    && matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine)
+            });
+            if let Some(arg) = misplaced {
+                command
+                    .error(
+                        ErrorKind::ArgumentConflict,
+                        format!(
+                            "'--{}' can't be used before the subcommand '{}'",
+                            arg.get_long().unwrap_or_default(),
+                            name
+                        ),
+                    )
+                    .exit();
+            }
+        }
+        Args::from_arg_matches(&matches).unwrap_or_else(|err| err.exit())
+    }
+}
+
+/// What to print besides the readings.
+#[derive(clap::Args)]
+struct OutputArgs {
+    /// Print link quality statistics every SECONDS
+    #[arg(long, value_name = "SECONDS")]
+    stats: Option<u64>,
+    /// Drop the transitional readings sent while the meter changes range:
+    /// unknown digits, single-reading spikes and unit flips
+    #[arg(long)]
+    deglitch: bool,
+    /// Print the changes of the meter state: mode, range, hold, overload...
+    #[arg(long)]
+    events: bool,
+    /// Print the uncertainty of the readings, from the accuracy of the meter
+    #[arg(long)]
+    uncertainty: bool,
+    /// Print the readings in the base unit of their mode (V, A, Ω...), or in
+    /// the units given for some modes, e.g. temperature=°F,dc-amps=mA
+    #[arg(long,
+        value_name = "MODE=UNIT,...",
+        num_args = 0..=1,
+        default_missing_value = ""
+    )]
+    units: Option<UnitPreferences>,
+    /// Print the difference of the readings from the first one
+    #[arg(long)]
+    relative: bool,
+    /// Print the difference of the readings from VALUE, in the base unit of
+    /// the mode of the first reading (V, A, Ω...)
+    #[arg(long, value_name = "VALUE", allow_negative_numbers = true)]
+    reference: Option<f64>,
+    /// Print statistics of the readings, per mode and unit, when done
+    #[arg(long)]
+    summary: bool,
+    #[command(flatten)]
+    settle: SettleArgs,
+    /// Only print readings that differ from the last one printed
+    #[arg(long)]
+    changes: bool,
+    /// With --changes, ignore value changes within TOLERANCE (2d or 0.5%)
+    #[arg(long, value_name = "TOLERANCE")]
+    deadband: Option<Tolerance>,
+    /// With --changes, print a reading at least every SECONDS
+    #[arg(long, value_name = "SECONDS")]
+    heartbeat: Option<u64>,
+    /// Print the trend of the readings of the last SECONDS: smoothed value
+    /// and rate of change
+    #[arg(long, value_name = "SECONDS")]
+    trend: Option<u64>,
+    /// With --trend, estimate when the reading reaches VALUE, in the base
+    /// unit of the mode (V, A, Ω...)
+    #[arg(long, value_name = "VALUE", allow_negative_numbers = true)]
+    target: Option<f64>,
+    /// Print a summary of the readings of every SECONDS instead of the readings
+    #[arg(long, value_name = "SECONDS")]
+    window: Option<u64>,
+    /// Raise the alarms described in FILE
+    #[arg(long, value_name = "FILE")]
+    alarms: Option<PathBuf>,
+    /// Sort the settled resistors or capacitors into the SERIES (E12, E24 or
+    /// E96) values
+    #[arg(long, value_name = "SERIES")]
+    bin: Option<ESeries>,
+    /// With --bin, accepted deviation from the nominal value, in percent
+    /// (10, 5 or 1 depending on the series by default)
+    #[arg(long, value_name = "PERCENT")]
+    bin_tolerance: Option<f64>,
+}
+
+/// When a value is considered settled.
+#[derive(clap::Args)]
+struct SettleArgs {
+    /// Report the value once READINGS consecutive readings agree
+    #[arg(long = "settle", value_name = "READINGS")]
+    readings: Option<usize>,
+    /// How much settled readings may differ, in digits (2d) or percent (0.5%)
+    #[arg(long, default_value = "2d")]
+    tolerance: Tolerance,
+    /// Ring the terminal bell when the value settles
+    #[arg(long)]
+    beep: bool,
+}
+
+impl SettleArgs {
+    /// Detects values settled over `readings` consecutive readings.
+    fn detector(&self, readings: usize) -> SettleDetector {
+        let detector = SettleDetector::new(readings, self.tolerance);
+        if self.beep {
+            detector.on_stable(|_| eprint!("\x07"))
+        } else {
+            detector
+        }
+    }
+}
+
+impl OutputArgs {
+    fn stats_interval(&self) -> Option<Duration> {
+        self.stats.map(Duration::from_secs)
+    }
+
+    /// Readings that must agree before a value is reported as stable, if
+    /// stable values are needed.
+    fn settle_readings(&self) -> Option<usize> {
+        self.settle.readings.or(self.bin.map(|_| 3))
+    }
+
+    fn component_sorter(&self) -> Option<ComponentSorter> {
+        let sorter = ComponentSorter::new(self.bin?);
+        Some(match self.bin_tolerance {
+            Some(percent) => sorter.tolerance(percent),
+            None => sorter,
+        })
+    }
+
+    fn relative_mode(&self) -> Option<RelativeMode> {
+        let mut relative = RelativeMode::new();
+        match (self.relative, self.reference) {
+            (_, Some(_)) => {}
+            (true, None) => relative.zero(),
+            (false, None) => return None,
+        }
+        Some(relative)
+    }
+
+    fn reading_filter(&self) -> Option<ReadingFilter> {
+        if !self.changes {
+            return None;
+        }
+        let mut filter = ReadingFilter::new();
+        if let Some(tolerance) = self.deadband {
+            filter = filter.deadband(tolerance);
+        }
+        if let Some(seconds) = self.heartbeat {
+            filter = filter.heartbeat(Duration::from_secs(seconds));
+        }
+        Some(filter)
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Connect to a meter and print its readings (default)
+    Monitor(OutputArgs),
+    /// List the available Bluetooth adapters
+    Adapters,
+    /// Print the readings of a simulated meter running a scenario file
+    Simulate {
+        scenario: PathBuf,
+        /// Run the scenario faster than real time
+        #[arg(long, default_value_t = 1.0)]
+        speed: f64,
+        /// Time between two frames, in milliseconds
+        #[arg(long, default_value_t = 500)]
+        interval: u64,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Log a battery's voltage and current until a cutoff, using two meters
+    /// (one on V, one on A)
+    Battery(battery::BatteryArgs),
+    /// Check a meter against reference standards and report whether it is
+    /// within its accuracy
+    Verify(verify::VerifyArgs),
+    /// Record a data point each time HOLD is pressed on the meter
+    Capture(capture::CaptureArgs),
+    /// Save the readings around a trigger condition, like a scope
+    Trigger(trigger::TriggerArgs),
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    println!("Scanning for devices...");
+    let args = Args::parse_checked();
+    let calibrations = args
+        .calibration
+        .as_ref()
+        .map(Calibrations::load)
+        .transpose()?;
+    let calibrations = calibrations.as_ref();
+
+    // Without a subcommand, the output options are given to the default monitor
+    match args.command.unwrap_or(Command::Monitor(args.output)) {
+        Command::Monitor(output) => monitor(args.adapter, calibrations, &output).await,
+        Command::Adapters => list_adapters().await,
+        Command::Simulate {
+            scenario,
+            speed,
+            interval,
+            output,
+        } => {
+            let transport = SimulatorTransport::new(Scenario::load(scenario)?)
+                .speed(speed)?
+                .interval(Duration::from_millis(interval))?;
+            let dmm = calibrate(DmmDevice::new(transport), calibrations, None);
+            dmm.connect().await?;
+            println!("Connected to simulated device");
+            println!();
+            run(dmm, &output).await
+        }
+        Command::Battery(battery) => {
+            if !battery.scenario.is_empty() {
+                let mut meters = Vec::new();
+                for scenario in &battery.scenario {
+                    let transport =
+                        SimulatorTransport::new(Scenario::load(scenario)?).speed(battery.speed)?;
+                    meters.push(calibrate(DmmDevice::new(transport), calibrations, None));
+                }
+                return battery::run(meters, &battery).await;
+            }
+
+            let manager = btleplug::platform::Manager::new().await?;
+            let Some(adapter) = open_adapter(&manager, args.adapter).await? else {
+                return Ok(());
+            };
+            println!("Scanning for devices on {}...", adapter.info);
+            let meters = btdmm_comm::scan_for_dmms(adapter.adapter)
+                .await?
+                .into_iter()
+                .map(|dmm| {
+                    let address = dmm.device().address().to_string();
+                    calibrate(dmm, calibrations, Some(&address))
+                })
+                .collect();
+            battery::run(meters, &battery).await
+        }
+        Command::Verify(verify) => {
+            let meter = open_meter(
+                verify.scenario.as_deref(),
+                verify.speed,
+                args.adapter,
+                calibrations,
+            )
+            .await?;
+            match meter {
+                Some(Meter::Simulated(dmm)) => verify::run(dmm, "simulated device", &verify).await,
+                Some(Meter::Bluetooth(dmm, address)) => verify::run(dmm, &address, &verify).await,
+                None => Ok(()),
+            }
+        }
+        Command::Capture(capture) => {
+            let meter = open_meter(
+                capture.scenario.as_deref(),
+                capture.speed,
+                args.adapter,
+                calibrations,
+            )
+            .await?;
+            match meter {
+                Some(Meter::Simulated(dmm)) => {
+                    capture::run(dmm, "simulated device", &capture).await
+                }
+                Some(Meter::Bluetooth(dmm, address)) => capture::run(dmm, &address, &capture).await,
+                None => Ok(()),
+            }
+        }
+        Command::Trigger(trigger) => {
+            let meter = open_meter(
+                trigger.scenario.as_deref(),
+                trigger.speed,
+                args.adapter,
+                calibrations,
+            )
+            .await?;
+            match meter {
+                Some(Meter::Simulated(dmm)) => {
+                    trigger::run(dmm, "simulated device", &trigger).await
+                }
+                Some(Meter::Bluetooth(dmm, address)) => trigger::run(dmm, &address, &trigger).await,
+                None => Ok(()),
+            }
+        }
+    }
+}
 
+async fn list_adapters() -> Result<(), Box<dyn Error>> {
     let manager = btleplug::platform::Manager::new().await?;
-    let adapter_list = manager.adapters().await?;
-    if adapter_list.is_empty() {
+    let adapters = btdmm_comm::list_adapters(&manager).await?;
+    if adapters.is_empty() {
         eprintln!("No Bluetooth adapters found");
-        return Ok(());
     }
-
-    if adapter_list.len() > 1 {
-        eprintln!("Multiple Bluetooth adapters found, using the first one");
+    for adapter in adapters {
+        println!("{}: {}", adapter.index, adapter.info);
     }
+    Ok(())
+}
+
+async fn monitor(
+    adapter: Option<AdapterSelector>,
+    calibrations: Option<&Calibrations>,
+    output: &OutputArgs,
+) -> Result<(), Box<dyn Error>> {
+    let manager = btleplug::platform::Manager::new().await?;
+    let Some(adapter) = open_adapter(&manager, adapter).await? else {
+        return Ok(());
+    };
 
-    let adapter = adapter_list.into_iter().next().unwrap();
+    println!("Scanning for devices on {}...", adapter.info);
 
-    let dmm = btdmm_comm::scan_for_dmm(adapter).await?;
+    let dmm = btdmm_comm::scan_for_dmm(adapter.adapter).await?;
+    let address = dmm.device().address().to_string();
+    let dmm = calibrate(dmm, calibrations, Some(&address));
     dmm.connect().await?;
 
     println!(
@@ -38,25 +383,360 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     println!();
 
-    loop {
-        let measurement = dmm.next_event(Duration::from_secs(5)).await?;
+    run(dmm, output).await
+}
+
+/// Corrects the readings of the meter with its calibration profile, if it has
+/// one.
+fn calibrate<P: Transport>(
+    dmm: DmmDevice<P>,
+    calibrations: Option<&Calibrations>,
+    address: Option<&str>,
+) -> DmmDevice<P> {
+    match calibrations.and_then(|c| c.for_device(address)) {
+        Some(profile) => dmm.with_calibration(profile.clone()),
+        None => dmm,
+    }
+}
+
+/// Meter a command runs on.
+enum Meter {
+    Simulated(DmmDevice<SimulatorTransport>),
+    /// A Bluetooth meter and its address.
+    Bluetooth(DmmDevice<btleplug::platform::Peripheral>, String),
+}
+
+/// Opens the simulated meter running `scenario` if there is one, or scans for
+/// a Bluetooth meter otherwise, correcting its readings with its calibration
+/// profile. `None` if there is no Bluetooth adapter.
+async fn open_meter(
+    scenario: Option<&Path>,
+    speed: f64,
+    adapter: Option<AdapterSelector>,
+    calibrations: Option<&Calibrations>,
+) -> Result<Option<Meter>, Box<dyn Error>> {
+    if let Some(scenario) = scenario {
+        let transport = SimulatorTransport::new(Scenario::load(scenario)?).speed(speed)?;
+        let dmm = calibrate(DmmDevice::new(transport), calibrations, None);
+        return Ok(Some(Meter::Simulated(dmm)));
+    }
 
-        match measurement.displayed_value {
-            DisplayValue::Text(text) => print!("{} ", text),
-            DisplayValue::Number(value) => print!("{} ", value),
+    let manager = btleplug::platform::Manager::new().await?;
+    let Some(adapter) = open_adapter(&manager, adapter).await? else {
+        return Ok(None);
+    };
+    println!("Scanning for devices on {}...", adapter.info);
+    let dmm = btdmm_comm::scan_for_dmm(adapter.adapter).await?;
+    let address = dmm.device().address().to_string();
+    let dmm = calibrate(dmm, calibrations, Some(&address));
+    Ok(Some(Meter::Bluetooth(dmm, address)))
+}
+
+/// Picks the adapter to scan with, or `None` if there is none.
+async fn open_adapter(
+    manager: &btleplug::platform::Manager,
+    adapter: Option<AdapterSelector>,
+) -> Result<Option<BluetoothAdapter>, Box<dyn Error>> {
+    if let Some(selector) = adapter {
+        return Ok(Some(btdmm_comm::select_adapter(manager, &selector).await?));
+    }
+
+    let mut adapters = btdmm_comm::list_adapters(manager).await?;
+    if adapters.is_empty() {
+        eprintln!("No Bluetooth adapters found");
+        return Ok(None);
+    }
+    if adapters.len() > 1 {
+        eprintln!("Multiple Bluetooth adapters found, using the first one (choose with --adapter)");
+    }
+    Ok(Some(adapters.remove(0)))
+}
+
+/// Prints the measurements until the meter goes away or Ctrl-C is pressed,
+/// then releases the meter.
+async fn run<P: Transport>(
+    mut dmm: DmmDevice<P>,
+    output: &OutputArgs,
+) -> Result<(), Box<dyn Error>> {
+    let mut tracker = StatsTracker::new();
+    let result = print_measurements(&dmm, output, &mut tracker).await;
+
+    if output.stats.is_some() {
+        print_link_stats(&dmm.link_stats());
+    }
+    if output.summary {
+        print_summary(&tracker);
+    }
+    io::stdout().flush()?;
+
+    disconnect(&mut dmm).await;
+
+    result
+}
+
+async fn disconnect<P: Transport>(dmm: &mut DmmDevice<P>) {
+    match tokio::time::timeout(Duration::from_secs(5), dmm.disconnect()).await {
+        Ok(Err(err)) => eprintln!("Failed to disconnect: {}", err),
+        Err(_) => eprintln!("Timed out while disconnecting"),
+        Ok(Ok(())) => {}
+    }
+}
+
+/// Ends the given measurement streams when Ctrl-C is pressed.
+fn abort_on_ctrl_c(handles: Vec<AbortHandle>) {
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            eprintln!("Interrupted, disconnecting...");
+            handles.iter().for_each(AbortHandle::abort);
         }
-        if let Some(unit) = measurement.value_unit {
-            print!("{} ", unit);
+    });
+}
+
+async fn print_measurements<P: Transport>(
+    dmm: &DmmDevice<P>,
+    output: &OutputArgs,
+    tracker: &mut StatsTracker,
+) -> Result<(), Box<dyn Error>> {
+    let (mut measurements, cancel) = dmm.measurements(Duration::from_secs(5));
+    abort_on_ctrl_c(vec![cancel]);
+    if output.deglitch {
+        measurements = GlitchFilter::new().apply(measurements);
+    }
+
+    let mut settle = output
+        .settle_readings()
+        .map(|readings| output.settle.detector(readings));
+    let mut filter = output.reading_filter();
+    let mut state = output.events.then(MeterState::new);
+    let mut trends = output
+        .trend
+        .map(|seconds| TrendTracker::new(Duration::from_secs(seconds)));
+    let mut sorter = output.component_sorter();
+    let mut relative = output.relative_mode();
+    let mut reference = output.reference;
+    let mut alarms = output.alarms.as_ref().map(AlarmEngine::load).transpose()?;
+    let mut windows = output
+        .window
+        .map(|seconds| WindowAggregator::tumbling(Duration::from_secs(seconds)))
+        .transpose()?;
+    let mut last_stats = Instant::now();
+    let mut result = Ok(());
+
+    while let Some(item) = measurements.next().await {
+        let timed = match item {
+            Ok(timed) => timed,
+            Err(err) if matches!(err.downcast_ref(), Some(DmmError::DeviceDisconnected)) => {
+                if output.stats.is_some() {
+                    print_link_stats(&dmm.link_stats());
+                }
+                result = Err(err);
+                break;
+            }
+            // Timeouts and invalid frames are counted in the link statistics
+            Err(err) => {
+                eprintln!("{}", err);
+                continue;
+            }
+        };
+        tracker.push(&timed);
+        if let Some(state) = &mut state {
+            for event in state.push(&timed) {
+                println!(
+                    "{}  {}",
+                    event.timestamp.with_timezone(&Local).format("%H:%M:%S"),
+                    event.kind
+                );
+            }
+        }
+        let delta = relative.as_mut().and_then(|relative| {
+            if let (Some(value), Some(mode)) = (reference, timed.measurement.mode()) {
+                relative.set(mode, value);
+                reference = None;
+            }
+            let had_reference = relative.reference().is_some();
+            let delta = relative.push(&timed);
+            if had_reference && relative.reference().is_none() {
+                println!("Reference cleared, the mode changed");
+            }
+            delta
+        });
+        let trend = trends.as_mut().and_then(|trends| trends.push(&timed));
+        let stable = settle.as_mut().and_then(|settle| settle.push(&timed));
+        if let Some(alarms) = &mut alarms {
+            // The actions of the rules report the alarms
+            alarms.push(&timed);
+        }
+        // Filtering only applies to the printed readings
+        if let Some(windows) = &mut windows {
+            windows.push(&timed).iter().for_each(print_window);
+        } else if filter.as_mut().is_none_or(|filter| filter.accept(&timed)) {
+            print_reading(&timed, delta.as_ref(), dmm.profile(), output);
+            if let Some(trend) = &trend {
+                print_trend(trend, output.target);
+            }
+        }
+
+        if let Some(stable) = stable {
+            println!(
+                "Stable: {} {}",
+                stable.reading.measurement.value().unwrap_or_default(),
+                stable.unit.as_deref().unwrap_or("")
+            );
+            if let Some(result) = sorter.as_mut().and_then(|s| s.check(&stable.reading)) {
+                println!("Bin: {}", result);
+            }
+        }
+
+        if let Some(interval) = output.stats_interval() {
+            if last_stats.elapsed() >= interval {
+                // Not every platform reports the RSSI of a connected device
+                let _ = dmm.sample_rssi().await;
+                print_link_stats(&dmm.link_stats());
+                last_stats = Instant::now();
+            }
         }
-        print!("   ");
+    }
+
+    if let Some(windows) = &mut windows {
+        windows.flush().iter().for_each(print_window);
+    }
+    if let Some(sorter) = &sorter {
+        println!();
+        for bin in sorter.tally() {
+            println!("{}", bin);
+        }
+    }
+
+    result
+}
+
+/// Prints a reading as asked by `output`, with its uncertainty according to
+/// `profile`, the one of the meter.
+fn print_reading(
+    timed: &TimedMeasurement,
+    delta: Option<&RelativeReading>,
+    profile: Option<&ModelProfile>,
+    output: &OutputArgs,
+) {
+    let measurement = &timed.measurement;
+    let displayed = measurement.quantity();
+    let quantity = match &output.units {
+        Some(units) if displayed.is_some() => units.normalize(measurement).or(displayed),
+        _ => displayed,
+    };
+    match (&measurement.displayed_value, quantity) {
+        (DisplayValue::Number(_), Some(quantity)) => {
+            let uncertainty = profile
+                .filter(|_| output.uncertainty)
+                .and_then(|profile| measurement.uncertainty(profile))
+                .and_then(|u| {
+                    let unit = measurement.value_unit.as_deref()?;
+                    u.convert(unit, &quantity.unit).ok()
+                });
+            match uncertainty {
+                Some(uncertainty) => print!("{} {} ", uncertainty, quantity.unit),
+                None => print!("{} ", quantity),
+            }
+        }
+        (DisplayValue::Number(value), None) => print!("{} ", value),
+        (DisplayValue::Text(text), _) => print!("{} ", text),
+    }
+    if let (DisplayValue::Text(_), Some(unit)) =
+        (&measurement.displayed_value, &measurement.value_unit)
+    {
+        print!("{} ", unit);
+    }
+    if let Some(raw) = timed.raw.as_ref().and_then(Measurement::value) {
+        print!("(raw {}) ", raw);
+    }
+    if let Some(delta) = delta {
+        print!("{} ", delta);
+    }
+    print!("   ");
+    print!(
+        "{:?}",
+        measurement
+            .displayed_icons
+            .iter()
+            .map(|i| format!("{:?}", i))
+            .collect::<Vec<String>>()
+    );
+    println!();
+}
+
+fn print_window(summary: &WindowSummary) {
+    let unit = summary.unit.as_deref().unwrap_or("");
+    print!(
+        "{} - {}  ",
+        summary.start.with_timezone(&Local).format("%H:%M:%S"),
+        summary.end.with_timezone(&Local).format("%H:%M:%S")
+    );
+    match (summary.min, summary.mean, summary.max, summary.last) {
+        (Some(min), Some(mean), Some(max), Some(last)) => print!(
+            "min {} {unit}, mean {:.4} {unit}, max {} {unit}, last {} {unit}",
+            min, mean, max, last
+        ),
+        _ => print!("no values"),
+    }
+    println!(", {} readings", summary.count);
+}
+
+fn print_trend(trend: &Trend, target: Option<f64>) {
+    print!("Trend: {}", trend);
+    if let Some(target) = target {
+        match trend.time_to(target) {
+            Some(time) => {
+                let seconds = time.as_secs();
+                print!(
+                    ", {} {} in {}:{:02}:{:02}",
+                    target,
+                    trend.mode.unit(),
+                    seconds / 3600,
+                    seconds / 60 % 60,
+                    seconds % 60
+                );
+            }
+            None => print!(", not heading to {} {}", target, trend.mode.unit()),
+        }
+    }
+    println!();
+}
+
+fn print_link_stats(stats: &LinkStats) {
+    print!("Link: {} frames", stats.frames);
+    if let (Some(rate), Some(jitter)) = (stats.frame_rate, stats.jitter) {
+        print!(" ({:.2} Hz, jitter {} ms)", rate, jitter.as_millis());
+    }
+    print!(
+        ", {} invalid, {} dropped, {} timeouts",
+        stats.invalid_frames, stats.dropped_frames, stats.timeouts
+    );
+    if let Some(rssi) = stats.rssi {
         print!(
-            "{:?}",
-            measurement
-                .displayed_icons
-                .iter()
-                .map(|i| format!("{:?}", i))
-                .collect::<Vec<String>>()
+            ", RSSI {} dBm (min {}, max {})",
+            rssi.last, rssi.min, rssi.max
+        );
+    }
+    println!();
+}
+
+fn print_summary(tracker: &StatsTracker) {
+    println!();
+    for stats in tracker.segments() {
+        let unit = stats.unit.as_deref().unwrap_or("");
+        match stats.mode {
+            Some(mode) => print!("{}", mode),
+            None => print!("Unknown mode"),
+        }
+        println!(" ({}), {} readings:", unit, stats.count);
+        // Segments always hold at least one reading
+        println!(
+            "    min {} {unit}, max {} {unit}, mean {:.4} {unit}, std dev {:.4} {unit}, p-p {:.4} {unit}",
+            stats.min().unwrap_or_default(),
+            stats.max().unwrap_or_default(),
+            stats.mean().unwrap_or_default(),
+            stats.std_dev().unwrap_or_default(),
+            stats.peak_to_peak().unwrap_or_default(),
         );
-        println!();
     }
 }
@@ -0,0 +1,137 @@
+use std::error::Error;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::Local;
+use futures::StreamExt;
+
+use btdmm_comm::{DmmDevice, DmmError, Transport, Trigger, TriggerBuffer, TriggeredCapture};
+
+use crate::{abort_on_ctrl_c, disconnect};
+
+#[derive(clap::Args)]
+pub struct TriggerArgs {
+    /// Condition starting a capture: "<mode> rising <value>", "<mode> falling
+    /// <value>", mode-change or overload, e.g. "dc-volts rising 3.3"
+    trigger: Trigger,
+    /// Save the frames of the captures to FILE, one per line with its time
+    /// (numbered FILE-1, FILE-2... with --count)
+    output: PathBuf,
+    /// Readings to keep before the trigger, in seconds
+    #[arg(long, default_value_t = 5.0, value_name = "SECONDS")]
+    pre: f64,
+    /// Readings to keep after the trigger, in seconds
+    #[arg(long, default_value_t = 5.0, value_name = "SECONDS")]
+    post: f64,
+    /// Number of captures to take before stopping
+    #[arg(long, default_value_t = 1)]
+    count: usize,
+    /// Use a simulated meter running the scenario FILE instead of a Bluetooth meter
+    #[arg(long, value_name = "FILE")]
+    pub scenario: Option<PathBuf>,
+    /// Run the scenario faster than real time
+    #[arg(long, default_value_t = 1.0)]
+    pub speed: f64,
+}
+
+impl TriggerArgs {
+    /// Path of the `index`th capture, counting from 1.
+    fn path(&self, index: usize) -> PathBuf {
+        if self.count <= 1 {
+            return self.output.clone();
+        }
+        let stem = self
+            .output
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy();
+        let name = match self.output.extension() {
+            Some(extension) => format!("{}-{}.{}", stem, index, extension.to_string_lossy()),
+            None => format!("{}-{}", stem, index),
+        };
+        self.output.with_file_name(name)
+    }
+}
+
+/// Waits for the trigger and saves the readings around it, until enough
+/// captures are taken or Ctrl-C is pressed, then releases the meter.
+pub async fn run<P: Transport>(
+    mut dmm: DmmDevice<P>,
+    device: &str,
+    args: &TriggerArgs,
+) -> Result<(), Box<dyn Error>> {
+    let seconds = |s: f64| Duration::try_from_secs_f64(s).map_err(|e| e.to_string());
+    let mut buffer = TriggerBuffer::new(args.trigger)
+        .pre_trigger(seconds(args.pre)?)
+        .post_trigger(seconds(args.post)?);
+
+    dmm.connect().await?;
+    println!("Connected to {}", device);
+    println!();
+
+    let result = capture(&dmm, &mut buffer, args).await;
+    io::stdout().flush()?;
+
+    disconnect(&mut dmm).await;
+
+    result
+}
+
+async fn capture<P: Transport>(
+    dmm: &DmmDevice<P>,
+    buffer: &mut TriggerBuffer,
+    args: &TriggerArgs,
+) -> Result<(), Box<dyn Error>> {
+    let (mut measurements, cancel) = dmm.measurements(Duration::from_secs(5));
+    abort_on_ctrl_c(vec![cancel]);
+
+    let mut saved = 0;
+    println!("Waiting for {}", buffer.trigger());
+    while saved < args.count {
+        let reading = match measurements.next().await {
+            Some(Ok(reading)) => reading,
+            Some(Err(err)) if matches!(err.downcast_ref(), Some(DmmError::DeviceDisconnected)) => {
+                // Keep what was captured after the trigger
+                if let Some(capture) = buffer.flush() {
+                    save(&capture, &args.path(saved + 1))?;
+                }
+                return Err(err);
+            }
+            Some(Err(err)) => {
+                eprintln!("{}", err);
+                continue;
+            }
+            // Interrupted
+            None => break,
+        };
+
+        let update = buffer.push(&reading);
+        if let Some(capture) = update.capture {
+            saved += 1;
+            save(&capture, &args.path(saved))?;
+        }
+        if update.fired && saved < args.count {
+            println!(
+                "{}  Triggered",
+                reading.timestamp.with_timezone(&Local).format("%H:%M:%S")
+            );
+        }
+    }
+
+    if let Some(capture) = buffer.flush().filter(|_| saved < args.count) {
+        save(&capture, &args.path(saved + 1))?;
+    }
+    Ok(())
+}
+
+fn save(capture: &TriggeredCapture, path: &Path) -> Result<(), Box<dyn Error>> {
+    capture.to_capture()?.save(path)?;
+    println!(
+        "Saved {} readings, {} before the trigger, to {}",
+        capture.readings.len(),
+        capture.trigger_index,
+        path.display()
+    );
+    Ok(())
+}
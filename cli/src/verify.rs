@@ -0,0 +1,142 @@
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use futures::StreamExt;
+use tokio::time::{self, Instant};
+
+use btdmm_comm::{DmmDevice, DmmError, SettleDetector, Transport, Verification};
+
+use crate::{SettleArgs, abort_on_ctrl_c, disconnect};
+
+/// Readings that must agree for a reference point, unless --settle says otherwise.
+const SETTLE_READINGS: usize = 5;
+
+#[derive(clap::Args)]
+pub struct VerifyArgs {
+    /// File listing the reference points to check, in order
+    points: PathBuf,
+    /// Save the verification report to FILE
+    #[arg(long, value_name = "FILE")]
+    report: Option<PathBuf>,
+    /// Skip the points not measured within SECONDS, leaving them out of the
+    /// result
+    #[arg(long, value_name = "SECONDS")]
+    timeout: Option<f64>,
+    /// Use a simulated meter running the scenario FILE instead of a Bluetooth meter
+    #[arg(long, value_name = "FILE")]
+    pub scenario: Option<PathBuf>,
+    /// Run the scenario faster than real time
+    #[arg(long, default_value_t = 1.0)]
+    pub speed: f64,
+    #[command(flatten)]
+    settle: SettleArgs,
+}
+
+/// Guides through the reference points, checking the settled reading of the
+/// meter at each, then prints the report and releases the meter.
+pub async fn run<P: Transport>(
+    mut dmm: DmmDevice<P>,
+    device: &str,
+    args: &VerifyArgs,
+) -> Result<(), Box<dyn Error>> {
+    let mut verification = Verification::load(&args.points)?;
+    let timeout = args
+        .timeout
+        .map(Duration::try_from_secs_f64)
+        .transpose()
+        .map_err(|e| e.to_string())?;
+    let mut settle = args
+        .settle
+        .detector(args.settle.readings.unwrap_or(SETTLE_READINGS));
+
+    dmm.connect().await?;
+    println!("Connected to {}", device);
+    println!();
+
+    let result = check_points(&dmm, &mut verification, &mut settle, timeout).await;
+
+    let device = match dmm.profile() {
+        Some(profile) => format!("{} ({})", device, profile.name),
+        None => device.to_string(),
+    };
+    let report = verification.report(&device);
+    println!();
+    println!("{}", report);
+    if let Some(path) = &args.report {
+        fs::write(path, format!("{}\n", report))?;
+        println!("Report saved to {}", path.display());
+    }
+    io::stdout().flush()?;
+
+    disconnect(&mut dmm).await;
+
+    result
+}
+
+async fn check_points<P: Transport>(
+    dmm: &DmmDevice<P>,
+    verification: &mut Verification,
+    settle: &mut SettleDetector,
+    timeout: Option<Duration>,
+) -> Result<(), Box<dyn Error>> {
+    let (mut measurements, cancel) = dmm.measurements(Duration::from_secs(5));
+    abort_on_ctrl_c(vec![cancel]);
+
+    let count = verification.points().len();
+    for index in 0..count {
+        let point = verification.points()[index].clone();
+        print!("Apply {} ({} of {})", point, index + 1, count);
+        match point.accuracy {
+            Some(accuracy) => println!(", accuracy {}", accuracy),
+            None => println!(),
+        }
+        io::stdout().flush()?;
+
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            let next = match deadline {
+                Some(deadline) => match time::timeout_at(deadline, measurements.next()).await {
+                    Ok(next) => next,
+                    Err(_) => {
+                        println!("  Skipped: not measured in time");
+                        verification.skip();
+                        settle.reset();
+                        break;
+                    }
+                },
+                None => measurements.next().await,
+            };
+            let reading = match next {
+                Some(Ok(reading)) => reading,
+                Some(Err(err))
+                    if matches!(err.downcast_ref(), Some(DmmError::DeviceDisconnected)) =>
+                {
+                    return Err(err);
+                }
+                Some(Err(err)) => {
+                    eprintln!("{}", err);
+                    continue;
+                }
+                // Interrupted
+                None => return Ok(()),
+            };
+            let Some(stable) = settle.push(&reading) else {
+                continue;
+            };
+            if let Some(profile) = dmm.profile() {
+                verification.set_profile(profile);
+            }
+            if let Some(result) = verification.push(&stable) {
+                println!("  {}", result);
+                // The next point may have the same value
+                settle.reset();
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}